@@ -24,10 +24,10 @@ fn main() {
     let entity_3 = entity_map.insert(3);
     let entity_4 = entity_map.insert(4);
 
-    entity_1.print();
-    entity_2.print();
-    entity_3.print();
-    entity_4.print();
+    println!("{}", entity_1);
+    println!("{}", entity_2);
+    println!("{}", entity_3);
+    println!("{}", entity_4);
 
     // Base Example
     let event_loop = EventLoop::new(); // The same across examples
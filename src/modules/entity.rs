@@ -1 +1,22 @@
-pub mod entity_map;
\ No newline at end of file
+pub mod access;
+pub mod archetype;
+pub mod bundle;
+pub mod change_detection;
+pub mod command;
+pub mod component;
+pub mod entity_map;
+pub mod event;
+pub mod executor;
+pub mod hierarchy;
+pub mod observer;
+pub mod query;
+pub mod reflect;
+pub mod removal_detection;
+pub mod resource;
+pub mod schedule;
+pub mod snapshot;
+pub mod storage;
+pub mod system;
+pub mod transform;
+pub mod value;
+pub mod world;
\ No newline at end of file
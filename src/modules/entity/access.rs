@@ -0,0 +1,106 @@
+use std::any::TypeId;
+use std::collections::{ BTreeMap, BTreeSet };
+
+use super::component::{ ComponentId, Components };
+
+/// Which Components and resources something reads and which it writes
+///
+/// Adding a read of something already written, or writing something twice, is an aliasing `&T` and
+/// `&mut T` and panics straight away
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Access {
+    reads: BTreeSet<ComponentId>,
+    writes: BTreeSet<ComponentId>,
+    resource_reads: BTreeMap<TypeId, &'static str>,
+    resource_writes: BTreeMap<TypeId, &'static str>,
+}
+
+impl Access {
+    pub fn new() -> Access {
+        Access::default()
+    }
+
+    pub fn add_read(&mut self, component: ComponentId, components: &Components) {
+        if self.writes.contains(&component) {
+            panic!(
+                "{} is accessed both immutably and mutably, this would alias a &mut",
+                components.info(component).name()
+            );
+        }
+        self.reads.insert(component);
+    }
+
+    pub fn add_write(&mut self, component: ComponentId, components: &Components) {
+        if self.reads.contains(&component) || self.writes.contains(&component) {
+            panic!(
+                "{} is accessed mutably more than once, this would alias a &mut",
+                components.info(component).name()
+            );
+        }
+        self.writes.insert(component);
+    }
+
+    pub fn add_resource_read(&mut self, resource: TypeId, name: &'static str) {
+        if self.resource_writes.contains_key(&resource) {
+            panic!("resource {} is accessed both immutably and mutably, this would alias a &mut", name);
+        }
+        self.resource_reads.insert(resource, name);
+    }
+
+    pub fn add_resource_write(&mut self, resource: TypeId, name: &'static str) {
+        if self.resource_reads.contains_key(&resource) || self.resource_writes.contains_key(&resource) {
+            panic!("resource {} is accessed mutably more than once, this would alias a &mut", name);
+        }
+        self.resource_writes.insert(resource, name);
+    }
+
+    /// Adds everything `other` accesses, panicking on the same conflicts as adding them one by one
+    pub fn extend(&mut self, other: &Access, components: &Components) {
+        for component in other.reads() {
+            self.add_read(component, components);
+        }
+        for component in other.writes() {
+            self.add_write(component, components);
+        }
+        for (resource, name) in other.resource_reads.iter() {
+            self.add_resource_read(*resource, name);
+        }
+        for (resource, name) in other.resource_writes.iter() {
+            self.add_resource_write(*resource, name);
+        }
+    }
+
+    pub fn reads(&self) -> impl Iterator<Item = ComponentId> + '_ {
+        self.reads.iter().copied()
+    }
+
+    pub fn writes(&self) -> impl Iterator<Item = ComponentId> + '_ {
+        self.writes.iter().copied()
+    }
+
+    pub fn has_resource_read(&self, resource: TypeId) -> bool {
+        self.resource_reads.contains_key(&resource)
+    }
+
+    pub fn has_resource_write(&self, resource: TypeId) -> bool {
+        self.resource_writes.contains_key(&resource)
+    }
+
+    pub fn has_read(&self, component: ComponentId) -> bool {
+        self.reads.contains(&component)
+    }
+
+    pub fn has_write(&self, component: ComponentId) -> bool {
+        self.writes.contains(&component)
+    }
+
+    /// True if both could run at the same time without one seeing the other's writes
+    pub fn is_compatible(&self, other: &Access) -> bool {
+        self.writes.is_disjoint(&other.reads)
+            && self.writes.is_disjoint(&other.writes)
+            && self.reads.is_disjoint(&other.writes)
+            && !other.resource_reads.keys().any(|r| self.resource_writes.contains_key(r))
+            && !other.resource_writes.keys().any(|r| self.resource_writes.contains_key(r))
+            && !other.resource_writes.keys().any(|r| self.resource_reads.contains_key(r))
+    }
+}
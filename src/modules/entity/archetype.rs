@@ -0,0 +1,446 @@
+use std::any::Any;
+use std::collections::HashMap;
+
+use super::change_detection::{ ComponentTicks, Tick };
+use super::component::{ ComponentId, Components };
+use super::entity_map::Entity;
+use super::storage::{ StorageType, SyncCell };
+
+/// Index of an Archetype inside Archetypes, the empty archetype is always first
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ArchetypeId(usize);
+
+impl ArchetypeId {
+    pub const EMPTY: ArchetypeId = ArchetypeId(0);
+
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// Where an Entity's Table Components currently live
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntityLocation {
+    pub archetype: ArchetypeId,
+    pub row: usize,
+}
+
+/// Type erased column so an Archetype can hold one per Component type
+pub trait AnyColumn: Send + Sync {
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Drops the value at `row`, the last value takes its place
+    fn swap_remove_drop(&mut self, row: usize);
+    /// Moves the value at `row` onto the end of `other`, the last value takes its place
+    fn swap_remove_into(&mut self, row: usize, other: &mut dyn AnyColumn);
+    /// Takes the value at `row` out as a `Box<T>`, the last value takes its place
+    fn swap_remove_any(&mut self, row: usize) -> Box<dyn Any>;
+    /// Clamps every change tick that is about to become too old to compare against `tick`
+    fn check_change_ticks(&mut self, tick: Tick);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// Contiguous values of a single Component type, one per row of the Archetype
+///
+/// Values sit in SyncCells so Queries can write to them through `&World`, each row's change ticks
+/// live in a parallel Vec
+pub struct Column<T> {
+    data: Vec<SyncCell<T>>,
+    ticks: Vec<SyncCell<ComponentTicks>>,
+}
+
+impl<T> Default for Column<T> {
+    fn default() -> Column<T> {
+        Column {
+            data: Vec::new(),
+            ticks: Vec::new(),
+        }
+    }
+}
+
+impl<T> Column<T> {
+    /// Adds a row for a value added at `tick`
+    pub fn push(&mut self, value: T, tick: Tick) {
+        self.data.push(SyncCell::new(value));
+        self.ticks.push(SyncCell::new(ComponentTicks::new(tick)));
+    }
+
+    pub fn swap_remove(&mut self, row: usize) -> T {
+        self.ticks.swap_remove(row);
+        self.data.swap_remove(row).into_inner()
+    }
+
+    pub fn get(&self, row: usize) -> Option<&T> {
+        // Mutable access through `&self` only happens via Queries, whose access checks keep it
+        // from overlapping with this borrow
+        self.data.get(row).map(|cell| unsafe { cell.deref() })
+    }
+
+    pub fn get_mut(&mut self, row: usize) -> Option<&mut T> {
+        self.data.get_mut(row).map(SyncCell::get_mut)
+    }
+
+    /// The value along with its change ticks, it is up to the caller to mark it changed
+    pub fn get_with_ticks_mut(&mut self, row: usize) -> Option<(&mut T, &mut ComponentTicks)> {
+        let value = self.data.get_mut(row)?.get_mut();
+        let ticks = self.ticks[row].get_mut();
+        Some((value, ticks))
+    }
+
+    pub fn cells(&self) -> &[SyncCell<T>] {
+        &self.data
+    }
+
+    pub fn ticks(&self) -> &[SyncCell<ComponentTicks>] {
+        &self.ticks
+    }
+}
+
+impl<T: Send + Sync + 'static> AnyColumn for Column<T> {
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn swap_remove_drop(&mut self, row: usize) {
+        self.data.swap_remove(row);
+        self.ticks.swap_remove(row);
+    }
+
+    fn swap_remove_into(&mut self, row: usize, other: &mut dyn AnyColumn) {
+        let other = other
+            .as_any_mut()
+            .downcast_mut::<Column<T>>()
+            .expect("moving a row between columns of different types");
+        other.data.push(self.data.swap_remove(row));
+        other.ticks.push(self.ticks.swap_remove(row));
+    }
+
+    fn swap_remove_any(&mut self, row: usize) -> Box<dyn Any> {
+        Box::new(self.swap_remove(row))
+    }
+
+    fn check_change_ticks(&mut self, tick: Tick) {
+        for ticks in self.ticks.iter_mut() {
+            ticks.get_mut().check_ticks(tick);
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Every Entity with exactly the same set of Components shares an Archetype
+///
+/// Table Components get one Column each, rows line up with `entities`. Components using one of the
+/// other StorageTypes are still part of the set but their values live outside the table
+pub struct Archetype {
+    id: ArchetypeId,
+    components: Vec<ComponentId>,
+    columns: Vec<Box<dyn AnyColumn>>,
+    column_indices: HashMap<ComponentId, usize>,
+    entities: Vec<Entity>,
+    add_edges: HashMap<ComponentId, ArchetypeId>,
+    remove_edges: HashMap<ComponentId, ArchetypeId>,
+}
+
+impl Archetype {
+    fn new(id: ArchetypeId, components: Vec<ComponentId>, registry: &Components) -> Archetype {
+        let mut columns = Vec::new();
+        let mut column_indices = HashMap::new();
+        for &component in &components {
+            let info = registry.info(component);
+            if info.storage_type() == StorageType::Table {
+                column_indices.insert(component, columns.len());
+                columns.push(info.new_column());
+            }
+        }
+
+        Archetype {
+            id,
+            components,
+            columns,
+            column_indices,
+            entities: Vec::new(),
+            add_edges: HashMap::new(),
+            remove_edges: HashMap::new(),
+        }
+    }
+
+    pub fn id(&self) -> ArchetypeId {
+        self.id
+    }
+
+    /// Sorted ids of every Component an Entity in this Archetype has
+    pub fn components(&self) -> &[ComponentId] {
+        &self.components
+    }
+
+    pub fn contains(&self, component: ComponentId) -> bool {
+        self.components.binary_search(&component).is_ok()
+    }
+
+    pub fn has_column(&self, component: ComponentId) -> bool {
+        self.column_indices.contains_key(&component)
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn column<T: 'static>(&self, component: ComponentId) -> Option<&Column<T>> {
+        let index = *self.column_indices.get(&component)?;
+        self.columns[index].as_any().downcast_ref::<Column<T>>()
+    }
+
+    pub fn column_mut<T: 'static>(&mut self, component: ComponentId) -> Option<&mut Column<T>> {
+        let index = *self.column_indices.get(&component)?;
+        self.columns[index].as_any_mut().downcast_mut::<Column<T>>()
+    }
+
+    fn column_dyn_mut(&mut self, component: ComponentId) -> Option<&mut dyn AnyColumn> {
+        let index = *self.column_indices.get(&component)?;
+        Some(&mut *self.columns[index])
+    }
+
+    /// Adds a row for the Entity, the caller is responsible for pushing a value to every column
+    pub fn push_entity(&mut self, entity: Entity) -> usize {
+        self.entities.push(entity);
+        self.entities.len() - 1
+    }
+
+    pub fn check_change_ticks(&mut self, tick: Tick) {
+        for column in self.columns.iter_mut() {
+            column.check_change_ticks(tick);
+        }
+    }
+
+    /// Drops the row, returns the Entity that was swapped into it if any
+    pub fn swap_remove(&mut self, row: usize) -> Option<Entity> {
+        for column in self.columns.iter_mut() {
+            column.swap_remove_drop(row);
+        }
+        self.entities.swap_remove(row);
+        self.entities.get(row).copied()
+    }
+}
+
+/// Owns every Archetype and the graph of add/remove edges between them
+pub struct Archetypes {
+    archetypes: Vec<Archetype>,
+    indices: HashMap<Vec<ComponentId>, ArchetypeId>,
+}
+
+impl Archetypes {
+    pub fn new(registry: &Components) -> Archetypes {
+        let mut archetypes = Archetypes {
+            archetypes: Vec::new(),
+            indices: HashMap::new(),
+        };
+        archetypes.get_or_insert(Vec::new(), registry);
+        archetypes
+    }
+
+    pub fn get(&self, id: ArchetypeId) -> &Archetype {
+        &self.archetypes[id.0]
+    }
+
+    pub fn get_mut(&mut self, id: ArchetypeId) -> &mut Archetype {
+        &mut self.archetypes[id.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.archetypes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.archetypes.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Archetype> {
+        self.archetypes.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Archetype> {
+        self.archetypes.iter_mut()
+    }
+
+    /// `components` must be sorted
+    pub fn get_or_insert(&mut self, components: Vec<ComponentId>, registry: &Components) -> ArchetypeId {
+        if let Some(id) = self.indices.get(&components) {
+            return *id;
+        }
+
+        let id = ArchetypeId(self.archetypes.len());
+        self.indices.insert(components.clone(), id);
+        self.archetypes.push(Archetype::new(id, components, registry));
+        id
+    }
+
+    /// The Archetype reached by adding `component` to `from`
+    pub fn with_component(&mut self, from: ArchetypeId, component: ComponentId, registry: &Components) -> ArchetypeId {
+        if let Some(id) = self.archetypes[from.0].add_edges.get(&component) {
+            return *id;
+        }
+
+        let mut components = self.archetypes[from.0].components.clone();
+        if let Err(position) = components.binary_search(&component) {
+            components.insert(position, component);
+        }
+        let to = self.get_or_insert(components, registry);
+        self.archetypes[from.0].add_edges.insert(component, to);
+        self.archetypes[to.0].remove_edges.insert(component, from);
+        to
+    }
+
+    /// The Archetype reached by removing `component` from `from`
+    pub fn without_component(&mut self, from: ArchetypeId, component: ComponentId, registry: &Components) -> ArchetypeId {
+        if let Some(id) = self.archetypes[from.0].remove_edges.get(&component) {
+            return *id;
+        }
+
+        let mut components = self.archetypes[from.0].components.clone();
+        if let Ok(position) = components.binary_search(&component) {
+            components.remove(position);
+        }
+        let to = self.get_or_insert(components, registry);
+        self.archetypes[from.0].remove_edges.insert(component, to);
+        self.archetypes[to.0].add_edges.insert(component, from);
+        to
+    }
+
+    /// The Archetype reached by adding every one of `components` to `from`, without creating the ones
+    /// in between
+    pub fn with_components(&mut self, from: ArchetypeId, components: &[ComponentId], registry: &Components) -> ArchetypeId {
+        let mut all = self.archetypes[from.0].components.clone();
+        all.extend_from_slice(components);
+        all.sort_unstable();
+        all.dedup();
+        self.get_or_insert(all, registry)
+    }
+
+    /// The Archetype reached by removing every one of `components` from `from`
+    pub fn without_components(&mut self, from: ArchetypeId, components: &[ComponentId], registry: &Components) -> ArchetypeId {
+        let mut remaining = self.archetypes[from.0].components.clone();
+        remaining.retain(|component| !components.contains(component));
+        self.get_or_insert(remaining, registry)
+    }
+
+    /// Moves the row from one Archetype to the end of another, returning the new row and the Entity
+    /// swapped into the old row
+    ///
+    /// Columns the destination does not have are handed to `on_missing`, which must swap remove the
+    /// row from them
+    pub fn move_row(
+        &mut self,
+        from: ArchetypeId,
+        row: usize,
+        to: ArchetypeId,
+        on_missing: &mut dyn FnMut(ComponentId, &mut dyn AnyColumn, usize),
+    ) -> (usize, Option<Entity>) {
+        assert_ne!(from, to, "cannot move a row within the same archetype");
+        let (src, dst) = if from.0 < to.0 {
+            let (left, right) = self.archetypes.split_at_mut(to.0);
+            (&mut left[from.0], &mut right[0])
+        } else {
+            let (left, right) = self.archetypes.split_at_mut(from.0);
+            (&mut right[0], &mut left[to.0])
+        };
+
+        for (&component, &index) in src.column_indices.iter() {
+            let column = &mut *src.columns[index];
+            match dst.column_dyn_mut(component) {
+                Some(other) => column.swap_remove_into(row, other),
+                None => on_missing(component, column, row),
+            }
+        }
+
+        let entity = src.entities.swap_remove(row);
+        let new_row = dst.push_entity(entity);
+        (new_row, src.entities.get(row).copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::entity::world::World;
+
+    #[derive(Debug, PartialEq)]
+    struct A(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct B(u32);
+
+    #[test]
+    fn entities_with_the_same_components_share_an_archetype() {
+        let mut world = World::new();
+        let first = world.spawn((A(1), B(1)));
+        let second = world.spawn((B(2), A(2)));
+        let other = world.spawn((A(3),));
+
+        let location = world.location(first).unwrap();
+        assert_eq!(world.location(second).unwrap().archetype, location.archetype);
+        assert_ne!(world.location(other).unwrap().archetype, location.archetype);
+
+        let archetype = world.archetypes().get(location.archetype);
+        assert_eq!(archetype.entities(), &[first, second]);
+        let a = world.components().id::<A>().unwrap();
+        let column = archetype.column::<A>(a).unwrap();
+        assert_eq!(column.get(0), Some(&A(1)));
+        assert_eq!(column.get(1), Some(&A(2)));
+    }
+
+    #[test]
+    fn moving_between_archetypes_keeps_every_value() {
+        let mut world = World::new();
+        let entities: Vec<_> = (0..3).map(|i| world.spawn((A(i),))).collect();
+
+        // The first row leaves, the last one is swapped into its place
+        world.insert(entities[0], B(10));
+        assert_eq!(world.location(entities[2]).unwrap().row, 0);
+        for (i, &entity) in entities.iter().enumerate() {
+            assert_eq!(world.get::<A>(entity), Some(&A(i as u32)));
+        }
+        assert_eq!(world.get::<B>(entities[0]), Some(&B(10)));
+
+        // And back again through the remove edge
+        let before = world.location(entities[1]).unwrap().archetype;
+        assert_eq!(world.remove::<B>(entities[0]), Some(B(10)));
+        assert_eq!(world.location(entities[0]).unwrap().archetype, before);
+        for (i, &entity) in entities.iter().enumerate() {
+            assert_eq!(world.get::<A>(entity), Some(&A(i as u32)));
+        }
+    }
+
+    #[test]
+    fn archetype_graph_edges_are_reused() {
+        let mut components = Components::default();
+        let a = components.init::<A>();
+        let b = components.init::<B>();
+        let mut archetypes = Archetypes::new(&components);
+
+        let with_a = archetypes.with_component(ArchetypeId::EMPTY, a, &components);
+        let with_ab = archetypes.with_component(with_a, b, &components);
+        assert_eq!(archetypes.with_components(ArchetypeId::EMPTY, &[b, a], &components), with_ab);
+        assert_eq!(archetypes.without_component(with_ab, b, &components), with_a);
+        assert_eq!(archetypes.without_components(with_ab, &[a, b], &components), ArchetypeId::EMPTY);
+        assert_eq!(archetypes.len(), 3);
+        assert_eq!(archetypes.get(with_ab).components(), &[a, b]);
+    }
+}
@@ -0,0 +1,158 @@
+use super::component::{ Component, ComponentId, Components };
+use super::world::{ BundleTaker, BundleWriter };
+
+/// A group of Components that is added to or removed from an Entity in one go
+///
+/// Implemented for tuples of up to eight Components and for structs declared with `bundle_struct!`.
+/// Inserting a Bundle moves the Entity straight to the Archetype holding all of it instead of
+/// through one Archetype per Component
+pub trait Bundle: Sized + Send + Sync + 'static {
+    /// Registers every Component of the Bundle, pushing their ids in the order `write` hands them out
+    fn component_ids(components: &mut Components, ids: &mut Vec<ComponentId>);
+
+    /// Hands each Component to the writer, in the same order as `component_ids`
+    fn write(self, writer: &mut BundleWriter<'_>);
+
+    /// Takes each Component from the taker, None if any of them was missing
+    ///
+    /// Every Component has to be taken even when an earlier one was missing, the taker drops
+    /// whatever is not claimed
+    fn take(taker: &mut BundleTaker<'_>) -> Option<Self>;
+}
+
+macro_rules! impl_tuple_bundle {
+    ($($name: ident),*) => {
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        impl<$($name: Component),*> Bundle for ($($name,)*) {
+            fn component_ids(components: &mut Components, ids: &mut Vec<ComponentId>) {
+                $(ids.push(components.init::<$name>());)*
+            }
+
+            fn write(self, writer: &mut BundleWriter<'_>) {
+                let ($($name,)*) = self;
+                $(writer.write($name);)*
+            }
+
+            fn take(taker: &mut BundleTaker<'_>) -> Option<Self> {
+                let ($($name,)*) = ($(taker.take::<$name>(),)*);
+                Some(($($name?,)*))
+            }
+        }
+    };
+}
+
+impl_tuple_bundle!();
+impl_tuple_bundle!(A);
+impl_tuple_bundle!(A, B);
+impl_tuple_bundle!(A, B, C);
+impl_tuple_bundle!(A, B, C, D);
+impl_tuple_bundle!(A, B, C, D, E);
+impl_tuple_bundle!(A, B, C, D, E, F);
+impl_tuple_bundle!(A, B, C, D, E, F, G);
+impl_tuple_bundle!(A, B, C, D, E, F, G, H);
+
+/// Implements Bundle for a struct with named fields, each field is one Component
+///
+/// ```ignore
+/// struct PlayerBundle { transform: Transform, velocity: Velocity, sprite: Sprite }
+/// bundle_struct!(PlayerBundle { transform: Transform, velocity: Velocity, sprite: Sprite });
+/// ```
+#[macro_export]
+macro_rules! bundle_struct {
+    ($t:ident { $($field:ident: $field_type:ty),* $(,)? }) => {
+        impl $crate::modules::entity::bundle::Bundle for $t {
+            fn component_ids(
+                components: &mut $crate::modules::entity::component::Components,
+                ids: &mut Vec<$crate::modules::entity::component::ComponentId>,
+            ) {
+                $(ids.push(components.init::<$field_type>());)*
+            }
+
+            fn write(self, writer: &mut $crate::modules::entity::world::BundleWriter<'_>) {
+                $(writer.write(self.$field);)*
+            }
+
+            fn take(taker: &mut $crate::modules::entity::world::BundleTaker<'_>) -> Option<$t> {
+                $(let $field = taker.take::<$field_type>();)*
+                Some($t {
+                    $($field: $field?,)*
+                })
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modules::entity::world::World;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+
+    #[derive(Debug, PartialEq)]
+    struct Velocity(i32);
+
+    #[derive(Debug, PartialEq)]
+    struct Name(&'static str);
+
+    #[derive(Debug, PartialEq)]
+    struct Mover {
+        position: Position,
+        velocity: Velocity,
+    }
+
+    crate::bundle_struct!(Mover { position: Position, velocity: Velocity });
+
+    fn mover(position: i32, velocity: i32) -> Mover {
+        Mover {
+            position: Position(position),
+            velocity: Velocity(velocity),
+        }
+    }
+
+    #[test]
+    fn tuples_and_structs_spawn_into_the_same_archetype() {
+        let mut world = World::new();
+        let from_tuple = world.spawn((Velocity(2), Position(1)));
+        let from_struct = world.spawn(mover(3, 4));
+        let empty = world.spawn(());
+
+        assert_eq!(world.location(from_tuple).unwrap().archetype, world.location(from_struct).unwrap().archetype);
+        assert_eq!(world.get::<Position>(from_tuple), Some(&Position(1)));
+        assert_eq!(world.get::<Velocity>(from_struct), Some(&Velocity(4)));
+        assert!(world.get::<Position>(empty).is_none());
+    }
+
+    #[test]
+    fn bundles_are_inserted_and_removed_whole() {
+        let mut world = World::new();
+        let entity = world.spawn((Name("a"), Position(0)));
+        world.insert_bundle(entity, mover(5, 6));
+        assert_eq!(world.get::<Position>(entity), Some(&Position(5)));
+        assert_eq!(world.get::<Name>(entity), Some(&Name("a")));
+
+        assert_eq!(world.remove_bundle::<Mover>(entity), Some(mover(5, 6)));
+        assert_eq!(world.remove_bundle::<Mover>(entity), None);
+        assert_eq!(world.get::<Name>(entity), Some(&Name("a")));
+
+        world.insert(entity, Position(7));
+        assert_eq!(world.remove_bundle::<(Position, Velocity)>(entity), None);
+        assert!(world.get::<Position>(entity).is_none());
+    }
+
+    #[test]
+    fn eight_component_tuples_spawn() {
+        let mut world = World::new();
+        let entity = world.spawn((1u8, 2u16, 3u32, 4u64, 5i8, 6i16, 7i32, 8i64));
+        assert_eq!(world.get::<u32>(entity), Some(&3));
+        assert_eq!(world.get::<i64>(entity), Some(&8));
+        assert_eq!(world.remove_bundle::<(u8, i64)>(entity), Some((1, 8)));
+    }
+
+    #[test]
+    #[should_panic(expected = "contains the same component more than once")]
+    fn duplicate_components_panic() {
+        let mut world = World::new();
+        world.spawn((Position(1), Position(2)));
+    }
+}
@@ -0,0 +1,289 @@
+use std::ops::{ Deref, DerefMut };
+
+/// How many ticks may pass before everything stored has to be checked for ticks that are about to
+/// wrap around, see `Schedule::run`
+pub const CHECK_TICK_THRESHOLD: u32 = 518_400_000;
+
+/// Ticks older than this are clamped, anything this old counts as changed before any System's last run
+///
+/// Leaves `CHECK_TICK_THRESHOLD` ticks of headroom on top of the age checks are allowed to reach
+/// between two of them, so a tick can never wrap around past the one it is compared with
+pub const MAX_CHANGE_AGE: u32 = u32::MAX - (2 * CHECK_TICK_THRESHOLD - 1);
+
+/// Point in time for change detection, the World's change tick advances every time a System runs
+///
+/// Ticks are compared relative to the current tick with wrapping arithmetic, so they keep working
+/// after the counter wraps around as long as no tick gets older than `MAX_CHANGE_AGE`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Tick(u32);
+
+impl Tick {
+    pub const fn new(tick: u32) -> Tick {
+        Tick(tick)
+    }
+
+    pub fn get(self) -> u32 {
+        self.0
+    }
+
+    /// True if this tick happened after `last_run`, as seen from `this_run`
+    pub fn is_newer_than(self, last_run: Tick, this_run: Tick) -> bool {
+        let ticks_since_change = this_run.0.wrapping_sub(self.0).min(MAX_CHANGE_AGE);
+        let ticks_since_run = this_run.0.wrapping_sub(last_run.0).min(MAX_CHANGE_AGE);
+        ticks_since_run > ticks_since_change
+    }
+
+    /// The tick `age` ticks before this one
+    pub fn relative_to(self, age: u32) -> Tick {
+        Tick(self.0.wrapping_sub(age))
+    }
+
+    /// Clamps the tick to `MAX_CHANGE_AGE` before `tick`, returns true if it had to be clamped
+    pub fn check_tick(&mut self, tick: Tick) -> bool {
+        if tick.0.wrapping_sub(self.0) > MAX_CHANGE_AGE {
+            *self = tick.relative_to(MAX_CHANGE_AGE);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// When a Component value was added and when it was last mutably accessed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ComponentTicks {
+    added: Tick,
+    changed: Tick,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: Tick) -> ComponentTicks {
+        ComponentTicks {
+            added: tick,
+            changed: tick,
+        }
+    }
+
+    pub fn added(&self) -> Tick {
+        self.added
+    }
+
+    pub fn changed(&self) -> Tick {
+        self.changed
+    }
+
+    pub fn is_added(&self, last_run: Tick, this_run: Tick) -> bool {
+        self.added.is_newer_than(last_run, this_run)
+    }
+
+    /// Being added counts as a change
+    pub fn is_changed(&self, last_run: Tick, this_run: Tick) -> bool {
+        self.changed.is_newer_than(last_run, this_run)
+    }
+
+    pub fn set_changed(&mut self, tick: Tick) {
+        self.changed = tick;
+    }
+
+    pub fn check_ticks(&mut self, tick: Tick) {
+        self.added.check_tick(tick);
+        self.changed.check_tick(tick);
+    }
+}
+
+/// Mutable reference to a Component value that marks it changed the first time it is written through
+pub struct Mut<'a, T> {
+    value: &'a mut T,
+    ticks: &'a mut ComponentTicks,
+    last_run: Tick,
+    this_run: Tick,
+}
+
+impl<'a, T> Mut<'a, T> {
+    pub fn new(value: &'a mut T, ticks: &'a mut ComponentTicks, last_run: Tick, this_run: Tick) -> Mut<'a, T> {
+        Mut {
+            value,
+            ticks,
+            last_run,
+            this_run,
+        }
+    }
+
+    /// True if the value was added since the System holding this last ran
+    pub fn is_added(&self) -> bool {
+        self.ticks.is_added(self.last_run, self.this_run)
+    }
+
+    /// True if the value was added or changed since the System holding this last ran
+    pub fn is_changed(&self) -> bool {
+        self.ticks.is_changed(self.last_run, self.this_run)
+    }
+
+    pub fn ticks(&self) -> &ComponentTicks {
+        self.ticks
+    }
+
+    pub fn set_changed(&mut self) {
+        self.ticks.set_changed(self.this_run);
+    }
+
+    /// Writes without marking the value changed, for bookkeeping nobody should react to
+    pub fn bypass_change_detection(&mut self) -> &mut T {
+        self.value
+    }
+
+    /// Marks the value changed and hands out the plain reference
+    pub fn into_inner(mut self) -> &'a mut T {
+        self.set_changed();
+        self.value
+    }
+}
+
+impl<'a, T> Deref for Mut<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T> DerefMut for Mut<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.set_changed();
+        self.value
+    }
+}
+
+/// Shared reference to a Component value that can tell whether it was added or changed
+pub struct Ref<'a, T> {
+    value: &'a T,
+    ticks: &'a ComponentTicks,
+    last_run: Tick,
+    this_run: Tick,
+}
+
+impl<'a, T> Ref<'a, T> {
+    pub fn new(value: &'a T, ticks: &'a ComponentTicks, last_run: Tick, this_run: Tick) -> Ref<'a, T> {
+        Ref {
+            value,
+            ticks,
+            last_run,
+            this_run,
+        }
+    }
+
+    /// True if the value was added since the System holding this last ran
+    pub fn is_added(&self) -> bool {
+        self.ticks.is_added(self.last_run, self.this_run)
+    }
+
+    /// True if the value was added or changed since the System holding this last ran
+    pub fn is_changed(&self) -> bool {
+        self.ticks.is_changed(self.last_run, self.this_run)
+    }
+
+    pub fn ticks(&self) -> &ComponentTicks {
+        self.ticks
+    }
+
+    pub fn into_inner(self) -> &'a T {
+        self.value
+    }
+}
+
+impl<'a, T> Deref for Ref<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::entity::query::{ Added, Changed };
+    use crate::modules::entity::resource::ResMut;
+    use crate::modules::entity::system::{ IntoSystem, Query, System };
+    use crate::modules::entity::world::World;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    #[derive(Default)]
+    struct Seen {
+        added: Vec<u32>,
+        changed: Vec<u32>,
+    }
+
+    fn watch(
+        added: Query<&Health, Added<Health>>,
+        changed: Query<&Health, Changed<Health>>,
+        mut seen: ResMut<Seen>,
+    ) {
+        seen.added = added.iter().map(|(_, health)| health.0).collect();
+        seen.changed = changed.iter().map(|(_, health)| health.0).collect();
+    }
+
+    fn seen(world: &World) -> (Vec<u32>, Vec<u32>) {
+        let seen = world.resource::<Seen>().unwrap();
+        let (mut added, mut changed) = (seen.added.clone(), seen.changed.clone());
+        added.sort_unstable();
+        changed.sort_unstable();
+        (added, changed)
+    }
+
+    #[test]
+    fn systems_see_what_was_added_or_changed_since_they_last_ran() {
+        let mut world = World::new();
+        world.insert_resource(Seen::default());
+        let first = world.spawn((Health(1),));
+        let second = world.spawn((Health(2),));
+        let mut system = watch.into_system();
+        system.initialize(&mut world);
+
+        system.run(&mut world);
+        assert_eq!(seen(&world), (vec![1, 2], vec![1, 2]));
+        system.run(&mut world);
+        assert_eq!(seen(&world), (vec![], vec![]));
+
+        world.get_mut::<Health>(first).unwrap().0 = 10;
+        world.spawn((Health(3),));
+        assert_eq!(world.get_mut::<Health>(second).unwrap().0, 2);
+        system.run(&mut world);
+        assert_eq!(seen(&world), (vec![3], vec![3, 10]));
+
+        world.get_mut::<Health>(second).unwrap().bypass_change_detection().0 = 20;
+        system.run(&mut world);
+        assert_eq!(seen(&world), (vec![], vec![]));
+    }
+
+    #[test]
+    fn ticks_compare_across_wraparound() {
+        let last_run = Tick::new(u32::MAX - 5);
+        let this_run = Tick::new(3);
+        assert!(Tick::new(u32::MAX - 1).is_newer_than(last_run, this_run));
+        assert!(Tick::new(1).is_newer_than(last_run, this_run));
+        assert!(!Tick::new(u32::MAX - 10).is_newer_than(last_run, this_run));
+        assert!(!Tick::new(u32::MAX - 5).is_newer_than(last_run, this_run));
+    }
+
+    #[test]
+    fn old_ticks_are_clamped() {
+        let now = Tick::new(MAX_CHANGE_AGE.wrapping_add(10));
+        let mut tick = Tick::new(0);
+        assert!(!tick.check_tick(Tick::new(MAX_CHANGE_AGE)));
+        assert!(tick.check_tick(now));
+        assert_eq!(tick, Tick::new(10));
+        assert!(!tick.check_tick(now));
+
+        let mut ticks = ComponentTicks::new(Tick::new(5));
+        ticks.set_changed(Tick::new(20));
+        let now = Tick::new(20u32.wrapping_add(MAX_CHANGE_AGE));
+        ticks.check_ticks(now);
+        assert_eq!(ticks.added(), now.relative_to(MAX_CHANGE_AGE));
+        assert_eq!(ticks.changed(), Tick::new(20));
+        // A clamped tick is never newer than a run that happened since
+        assert!(!ticks.is_added(Tick::new(100), now));
+    }
+}
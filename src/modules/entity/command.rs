@@ -0,0 +1,230 @@
+use std::mem::take;
+
+use super::bundle::Bundle;
+use super::component::Component;
+use super::entity_map::Entity;
+use super::resource::Resource;
+use super::system::{ SystemMeta, SystemParam };
+use super::world::World;
+
+type Command = Box<dyn FnOnce(&mut World) + Send + Sync>;
+
+/// Changes to the World recorded while it is borrowed, applied in order once it no longer is
+#[derive(Default)]
+pub struct CommandQueue {
+    commands: Vec<Command>,
+}
+
+impl CommandQueue {
+    pub fn push(&mut self, command: impl FnOnce(&mut World) + Send + Sync + 'static) {
+        self.commands.push(Box::new(command));
+    }
+
+    /// Spawns every reserved Entity and then runs the recorded commands in the order they were pushed
+    pub fn apply(&mut self, world: &mut World) {
+        world.flush();
+        for command in take(&mut self.commands) {
+            command(world);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+/// System parameter recording spawns, despawns and Component changes
+///
+/// Nothing happens until the end of the stage, but spawned Entities are reserved straight away so they
+/// can be used in later commands. Commands aimed at an Entity that is gone by then are skipped
+pub struct Commands<'w, 's> {
+    queue: &'s mut CommandQueue,
+    world: &'w World,
+}
+
+impl<'w, 's> Commands<'w, 's> {
+    pub fn new(queue: &'s mut CommandQueue, world: &'w World) -> Commands<'w, 's> {
+        Commands {
+            queue,
+            world,
+        }
+    }
+
+    /// Reserves a new Entity and records inserting the Bundle on it
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> EntityCommands<'_, 'w, 's> {
+        let mut entity = self.spawn_empty();
+        entity.insert_bundle(bundle);
+        entity
+    }
+
+    /// Reserves a new Entity without any Components
+    pub fn spawn_empty(&mut self) -> EntityCommands<'_, 'w, 's> {
+        let entity = self.world.reserve_entity();
+        self.entity(entity)
+    }
+
+    /// Records commands for an Entity that already exists or has been reserved
+    pub fn entity(&mut self, entity: Entity) -> EntityCommands<'_, 'w, 's> {
+        EntityCommands {
+            entity,
+            commands: self,
+        }
+    }
+
+    pub fn despawn(&mut self, entity: Entity) {
+        self.add(move |world| {
+            world.despawn(entity);
+        });
+    }
+
+    pub fn insert_resource<R: Resource>(&mut self, value: R) {
+        self.add(move |world| {
+            world.insert_resource(value);
+        });
+    }
+
+    pub fn remove_resource<R: Resource>(&mut self) {
+        self.add(|world| {
+            world.remove_resource::<R>();
+        });
+    }
+
+    /// Records an arbitrary change to the World
+    pub fn add(&mut self, command: impl FnOnce(&mut World) + Send + Sync + 'static) {
+        self.queue.push(command);
+    }
+}
+
+/// Commands for a single Entity, see `Commands::entity`
+pub struct EntityCommands<'a, 'w, 's> {
+    entity: Entity,
+    commands: &'a mut Commands<'w, 's>,
+}
+
+impl<'a, 'w, 's> EntityCommands<'a, 'w, 's> {
+    pub fn id(&self) -> Entity {
+        self.entity
+    }
+
+    pub fn insert<C: Component>(&mut self, component: C) -> &mut EntityCommands<'a, 'w, 's> {
+        let entity = self.entity;
+        self.commands.add(move |world| {
+            if world.contains(entity) {
+                world.insert(entity, component);
+            }
+        });
+        self
+    }
+
+    pub fn remove<C: Component>(&mut self) -> &mut EntityCommands<'a, 'w, 's> {
+        let entity = self.entity;
+        self.commands.add(move |world| {
+            world.remove::<C>(entity);
+        });
+        self
+    }
+
+    pub fn insert_bundle<B: Bundle>(&mut self, bundle: B) -> &mut EntityCommands<'a, 'w, 's> {
+        let entity = self.entity;
+        self.commands.add(move |world| {
+            if world.contains(entity) {
+                world.insert_bundle(entity, bundle);
+            }
+        });
+        self
+    }
+
+    pub fn remove_bundle<B: Bundle>(&mut self) -> &mut EntityCommands<'a, 'w, 's> {
+        let entity = self.entity;
+        self.commands.add(move |world| {
+            world.remove_bundle::<B>(entity);
+        });
+        self
+    }
+
+    pub fn despawn(&mut self) {
+        self.commands.despawn(self.entity);
+    }
+
+    pub fn commands(&mut self) -> &mut Commands<'w, 's> {
+        self.commands
+    }
+}
+
+unsafe impl<'a, 'b> SystemParam for Commands<'a, 'b> {
+    type State = CommandQueue;
+    type Item<'w, 's> = Commands<'w, 's>;
+
+    // Reserving Entities is atomic, so Commands never conflict with anything
+    fn init_state(_world: &mut World, _meta: &mut SystemMeta) -> CommandQueue {
+        CommandQueue::default()
+    }
+
+    unsafe fn get_param<'w, 's>(state: &'s mut CommandQueue, _meta: &SystemMeta, world: &'w World) -> Self::Item<'w, 's> {
+        Commands::new(state, world)
+    }
+
+    fn apply(state: &mut CommandQueue, world: &mut World) {
+        state.apply(world);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::entity::schedule::{ IntoSystemDescriptor, Stage };
+    use crate::modules::entity::system::Query;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct Seen(usize);
+
+    #[test]
+    fn commands_are_applied_at_the_end_of_the_stage() {
+        let mut world = World::new();
+        world.insert_resource(Seen(usize::MAX));
+        let mut stage = Stage::new();
+        stage
+            .add_system((|mut commands: Commands| {
+                let entity = commands.spawn((Health(1),)).id();
+                commands.entity(entity).insert(Health(2));
+            }).label("spawn"))
+            .add_system((|query: Query<&Health>, mut commands: Commands| {
+                let count = query.iter().count();
+                commands.insert_resource(Seen(count));
+            }).after("spawn"));
+        stage.run("test", &mut world).unwrap();
+
+        assert_eq!(world.resource::<Seen>(), Some(&Seen(0)));
+        let mut query = world.query::<&Health>();
+        assert_eq!(query.iter(&world).map(|(_, health)| health.0).collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn queue_runs_commands_in_order_and_skips_missing_entities() {
+        let mut world = World::new();
+        let gone = world.spawn((Health(1),));
+        let mut queue = CommandQueue::default();
+        let reserved = {
+            let mut commands = Commands::new(&mut queue, &world);
+            commands.despawn(gone);
+            commands.entity(gone).insert(Health(5));
+            let mut reserved = commands.spawn_empty();
+            reserved.insert(Health(2)).remove::<Health>().insert(Health(3));
+            reserved.id()
+        };
+        assert_eq!(queue.len(), 5);
+        assert!(!world.contains(reserved));
+
+        queue.apply(&mut world);
+        assert!(queue.is_empty());
+        assert!(!world.contains(gone));
+        assert_eq!(world.get::<Health>(reserved), Some(&Health(3)));
+    }
+}
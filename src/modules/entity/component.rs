@@ -0,0 +1,123 @@
+use std::any::{ type_name, TypeId };
+use std::collections::HashMap;
+
+use super::archetype::{ AnyColumn, Column };
+use super::storage::{ AnyStorage, ComponentCell, StorageType };
+
+/// Anything that can be stored against an Entity in the World
+/// 
+/// Components have to be shareable across threads so Systems can eventually be run in parallel
+pub trait Component: Send + Sync + 'static {}
+
+impl<T: Send + Sync + 'static> Component for T {}
+
+/// Dense id handed out to each Component type the first time the World sees it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ComponentId(usize);
+
+impl ComponentId {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// Everything the World needs to know to store a Component without knowing its type
+pub struct ComponentInfo {
+    id: ComponentId,
+    name: &'static str,
+    type_id: TypeId,
+    storage_type: StorageType,
+    new_column: fn() -> Box<dyn AnyColumn>,
+    new_storage: fn(StorageType) -> Box<dyn AnyStorage>,
+}
+
+impl ComponentInfo {
+    pub fn id(&self) -> ComponentId {
+        self.id
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn type_id(&self) -> TypeId {
+        self.type_id
+    }
+
+    pub fn storage_type(&self) -> StorageType {
+        self.storage_type
+    }
+
+    /// Creates an empty archetype column for this Component
+    pub fn new_column(&self) -> Box<dyn AnyColumn> {
+        (self.new_column)()
+    }
+
+    /// Creates the out of table storage for this Component, only meaningful for non Table types
+    pub fn new_storage(&self) -> Box<dyn AnyStorage> {
+        (self.new_storage)(self.storage_type)
+    }
+}
+
+/// Registry of every Component type the World knows about
+#[derive(Default)]
+pub struct Components {
+    infos: Vec<ComponentInfo>,
+    indices: HashMap<TypeId, ComponentId>,
+}
+
+impl Components {
+    /// Returns the id for C, registering it with the default StorageType if it is new
+    pub fn init<C: Component>(&mut self) -> ComponentId {
+        match self.get_id(TypeId::of::<C>()) {
+            Some(id) => id,
+            None => self.push::<C>(StorageType::default()),
+        }
+    }
+
+    /// Registers C with the given StorageType, returns None if C was already registered
+    pub fn register<C: Component>(&mut self, storage_type: StorageType) -> Option<ComponentId> {
+        match self.get_id(TypeId::of::<C>()) {
+            Some(_) => None,
+            None => Some(self.push::<C>(storage_type)),
+        }
+    }
+
+    fn push<C: Component>(&mut self, storage_type: StorageType) -> ComponentId {
+        let id = ComponentId(self.infos.len());
+        self.infos.push(ComponentInfo {
+            id,
+            name: type_name::<C>(),
+            type_id: TypeId::of::<C>(),
+            storage_type,
+            new_column: || Box::new(Column::<C>::default()),
+            new_storage: |storage_type| Box::new(storage_type.new_storage::<ComponentCell<C>>()),
+        });
+        self.indices.insert(TypeId::of::<C>(), id);
+        id
+    }
+
+    pub fn get_id(&self, type_id: TypeId) -> Option<ComponentId> {
+        self.indices.get(&type_id).copied()
+    }
+
+    pub fn id<C: Component>(&self) -> Option<ComponentId> {
+        self.get_id(TypeId::of::<C>())
+    }
+
+    pub fn info(&self, id: ComponentId) -> &ComponentInfo {
+        &self.infos[id.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.infos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.infos.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ComponentInfo> {
+        self.infos.iter()
+    }
+}
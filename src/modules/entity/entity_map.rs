@@ -1,6 +1,12 @@
 use std::vec::Vec;
-use std::cmp::max;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::iter::{ Enumerate, FusedIterator };
 use std::mem::replace;
+use std::slice;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::vec;
 
 /// Attempting to use Generational Index to implement the backbone of the SoA for Systems to query
 /// 
@@ -13,45 +19,184 @@ use std::mem::replace;
 /// In generational_arena it appears only data on the same generation is returned, it appears this is
 /// how the DS works to maintain synchronicity
 /// 
+/// Every slot carries its own generation, it only advances when that slot is freed so an Entity is
+/// valid exactly as long as the slot it points at has not been recycled
+/// 
 /// Shamelessly stolen from https://github.com/fitzgen/generational-arena/blob/master/src/lib.rs
 /// 
+/// Entities can also be reserved through `&self` from any number of threads, see `reserve_entity`
+/// 
+/// A map made with `with_capcity(n).fixed()` allocates once and never grows, `insert` panics on a
+/// full one instead and `try_insert` reports why it is full
+/// 
+/// A slot whose generation would overflow is retired for good rather than wrapping back around, which
+/// would make ancient Entities that still point at it valid again
+/// 
+/// Which freed slot gets handed out next is up to the RecyclePolicy
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct EntityMap<T> {
     items: Vec<EntityEntry<T>>,
-    generational_index: u64,
     free_list_head: Option<usize>,
+    /// Only kept so Fifo can append, None exactly when the free list is empty
+    free_list_tail: Option<usize>,
     len: usize,
+    policy: RecyclePolicy,
+    /// Counts `advance_frame` calls
+    frame: u64,
+    /// Slots freed under Quarantine with the frame they were freed in, oldest first
+    quarantine: VecDeque<(u64, usize)>,
+    /// Never grows past the capacity it was made with
+    fixed: bool,
+    /// Slots that used up their generations, they are off the free list for good
+    retired: usize,
+    /// The last generation a slot can have, always one before `DANGLING_GENERATION` outside of tests
+    max_generation: u32,
+    /// How far `reserve_entity` has got along the free list, `NO_SLOT` once it ran off the end. Equal
+    /// to `free_list_head` whenever nothing is reserved
+    reserve_cursor: AtomicUsize,
+    /// Reserved slots past the end of `items`
+    reserved_past_end: AtomicUsize,
 }
 
+/// In which order freed slots are handed out again
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecyclePolicy {
+    /// The slot freed last is reused first, keeps the map dense but generations climb fastest on
+    /// the slots that see the most churn
+    #[default]
+    Lifo,
+    /// The slot freed first is reused first, spreading the generations over every slot
+    Fifo,
+    /// Freed slots are held back until `advance_frame` has been called this many times, then they
+    /// are reused in the order they were freed. For when others, e.g. networked clients, may still
+    /// refer to Entities that were only just removed
+    Quarantine(u32),
+}
+
+/// Why an Entity could not be handed out without growing the map
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InsertError {
+    /// Every slot is taken, or taken and retired, removing an Entity makes room again
+    CapacityExhausted,
+    /// Every slot is retired, nothing but growing makes room again
+    GenerationExhausted,
+    /// Nothing is free but some slots are held back by Quarantine, `advance_frame` releases them
+    Quarantined,
+}
+
+impl fmt::Display for InsertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InsertError::CapacityExhausted => write!(f, "entity map is at its fixed capacity"),
+            InsertError::GenerationExhausted => write!(f, "entity map has no slots left that are not retired"),
+            InsertError::Quarantined => write!(f, "entity map has no free slots outside of quarantine"),
+        }
+    }
+}
+
+impl Error for InsertError {}
+
+/// `None` for the atomic free list cursor
+const NO_SLOT: usize = usize::MAX;
+
+fn encode_slot(slot: Option<usize>) -> usize {
+    slot.unwrap_or(NO_SLOT)
+}
+
+fn decode_slot(slot: usize) -> Option<usize> {
+    if slot == NO_SLOT {
+        None
+    } else {
+        Some(slot)
+    }
+}
+
+/// No slot is ever handed out with this generation, so an Entity carrying it never refers to anything
+pub const DANGLING_GENERATION: u32 = u32::MAX;
+
 /// This is the Index
-#[derive(Clone, Debug)]
+/// 
+/// Ordering compares the index first and the generation second. Packed into a u64 the index takes the
+/// low 32 bits and the generation the high 32 bits
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Entity {
     index: usize,
-    generational_index: u64,
+    generational_index: u32,
 }
 
 #[derive(Clone,Debug)]
 enum EntityEntry<T> {
-    Free { next_free: Option<usize> },
-    Occupied { generational_index: u64, value: T },
+    /// `generational_index` is the generation the next occupant of this slot will receive
+    Free { next_free: Option<usize>, generational_index: u32 },
+    Occupied { generational_index: u32, value: T },
+    /// Went through every generation, never handed out again
+    Retired,
 }
 
 impl Entity {
-    pub fn new(a: usize, b: u64) -> Entity {
+    pub fn new(a: usize, b: u32) -> Entity {
         Entity {
             index: a,
             generational_index: b
         }
     }
 
-    pub fn print(&self) {
-        println!("Index: {} | Generation: {}", self.index, self.generational_index)
+    /// An Entity no EntityMap ever hands out, distinct for every `n`. For references that have to point
+    /// somewhere but must never hit anything
+    pub fn dangling(n: usize) -> Entity {
+        Entity::new(n, DANGLING_GENERATION)
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generational_index
+    }
+
+    /// Packs the Entity into a single u64 for FFI, the wire and save files
+    pub fn to_bits(self) -> u64 {
+        assert!(self.index <= u32::MAX as usize, "entity index {} does not fit in 32 bits", self.index);
+        (self.generational_index as u64) << 32 | self.index as u64
+    }
+
+    pub fn from_bits(bits: u64) -> Entity {
+        Entity {
+            index: (bits & u32::MAX as u64) as usize,
+            generational_index: (bits >> 32) as u32,
+        }
+    }
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generational_index)
     }
 }
 
 const DEFAULT_CAPACITY: usize = 4;
 
+impl<T: Clone> Clone for EntityMap<T> {
+    fn clone(&self) -> EntityMap<T> {
+        EntityMap {
+            items: self.items.clone(),
+            free_list_head: self.free_list_head,
+            free_list_tail: self.free_list_tail,
+            len: self.len,
+            policy: self.policy,
+            frame: self.frame,
+            quarantine: self.quarantine.clone(),
+            fixed: self.fixed,
+            retired: self.retired,
+            max_generation: self.max_generation,
+            reserve_cursor: AtomicUsize::new(self.reserve_cursor.load(Ordering::Acquire)),
+            reserved_past_end: AtomicUsize::new(self.reserved_past_end.load(Ordering::Acquire)),
+        }
+    }
+}
+
 impl<T> Default for EntityMap<T> {
     fn default() -> EntityMap<T> {
         EntityMap::new()
@@ -64,57 +209,115 @@ impl<T> EntityMap<T> {
     }
 
     pub fn with_capcity(n: usize) -> EntityMap<T> {
-        let n = max(n, 1);
         let mut entity_map = EntityMap {
             items: Vec::new(),
-            generational_index: 0,
             free_list_head: None,
+            free_list_tail: None,
             len: 0,
+            policy: RecyclePolicy::default(),
+            frame: 0,
+            quarantine: VecDeque::new(),
+            fixed: false,
+            retired: 0,
+            max_generation: DANGLING_GENERATION - 1,
+            reserve_cursor: AtomicUsize::new(NO_SLOT),
+            reserved_past_end: AtomicUsize::new(0),
         };
         entity_map.reserve(n);
         entity_map
     }
 
+    /// Keeps the map at the slots it has, e.g. `EntityMap::with_capcity(n).fixed()`, so nothing is
+    /// allocated after construction. With a capacity of 0 every insert fails
+    pub fn fixed(mut self) -> EntityMap<T> {
+        self.fixed = true;
+        self
+    }
+
+    pub fn is_fixed(&self) -> bool {
+        self.fixed
+    }
+
+    pub fn recycle_policy(&self) -> RecyclePolicy {
+        self.policy
+    }
+
+    /// Applies to slots freed from now on. Leaving Quarantine releases every slot it still holds back
+    pub fn set_recycle_policy(&mut self, policy: RecyclePolicy) {
+        self.assert_flushed();
+        self.policy = policy;
+        if !matches!(policy, RecyclePolicy::Quarantine(_)) {
+            while let Some((_, index)) = self.quarantine.pop_front() {
+                self.push_free_back(index);
+            }
+        }
+    }
+
+    /// Starts a new frame, releasing the slots whose Quarantine is over onto the free list
+    pub fn advance_frame(&mut self) {
+        self.assert_flushed();
+        self.frame += 1;
+        let frames = match self.policy {
+            RecyclePolicy::Quarantine(frames) => frames as u64,
+            _ => return,
+        };
+        while let Some(&(freed, index)) = self.quarantine.front() {
+            if self.frame - freed < frames {
+                break;
+            }
+            self.quarantine.pop_front();
+            self.push_free_back(index);
+        }
+    }
+
+    /// Number of freed slots held back by Quarantine
+    pub fn quarantined(&self) -> usize {
+        self.quarantine.len()
+    }
+
+    /// Stores the value in a free slot without ever growing the map, if there is none the value is
+    /// handed back along with the reason
     #[inline]
-    pub fn try_insert(&mut self, value: T) -> Result<Entity, T> {
+    pub fn try_insert(&mut self, value: T) -> Result<Entity, (InsertError, T)> {
         match self.try_alloc_next_index() {
-            None => Err(value),
-            Some(index) => {
-                self.items[index.index] = EntityEntry::Occupied {
-                    generational_index: self.generational_index,
-                    value
-                };
-                Ok(index)
-            }
+            Some(index) => Ok(self.occupy(index, value)),
+            None => Err((self.exhausted(), value)),
         }
     }
 
     #[inline]
     pub fn try_alloc_next_index(&mut self) -> Option<Entity> {
+        self.assert_flushed();
         match self.free_list_head {
             None => None,
             Some(i) => match self.items[i] {
-                EntityEntry::Occupied { .. } => panic!("corrupt free list"),
-                EntityEntry::Free { next_free } => {
-                    self.free_list_head = next_free;
+                EntityEntry::Occupied { .. } | EntityEntry::Retired => panic!("corrupt free list"),
+                EntityEntry::Free { next_free, generational_index } => {
+                    self.set_free_list_head(next_free);
                     self.len += 1;
                     Some(Entity {
                         index: i,
-                        generational_index: self.generational_index
+                        generational_index
                     })
                 }
             }
         }
     }
 
+    /// Grows the map when `try_insert` fails, a fixed capacity map panics instead
     #[inline]
     pub fn insert(&mut self, value: T) -> Entity {
         match self.try_insert(value) {
             Ok(i) => i,
-            Err(value) => self.insert_slow_path(value)
+            Err((error, _)) if self.fixed => panic!("{}", error),
+            Err((_, value)) => self.insert_slow_path(value),
         }
     }
 
+    /// Doubles the number of slots and stores the value in one of the new ones, what `insert` falls
+    /// back on once the free list is empty
+    ///
+    /// Panics for a fixed capacity map
     #[inline]
     pub fn insert_slow_path(&mut self, value: T) -> Entity {
         let len = if self.capacity() == 0 {
@@ -123,36 +326,256 @@ impl<T> EntityMap<T> {
             self.items.len()
         };
         self.reserve(len);
-        self.try_insert(value)
-            .map_err(|_| ())
-            .expect("inserting will always succeed after reserving additional space")
+        let index = self.try_alloc_next_index()
+            .expect("allocating will always succeed after reserving additional space");
+        self.occupy(index, value)
+    }
+
+    fn occupy(&mut self, index: Entity, value: T) -> Entity {
+        self.items[index.index] = EntityEntry::Occupied {
+            generational_index: index.generational_index,
+            value
+        };
+        index
+    }
+
+    /// Hands out an Entity without needing `&mut self`, any number of threads can reserve at once
+    ///
+    /// Free slots are claimed first by walking the free list with a compare and swap, the free list
+    /// itself is left alone while anything is reserved so the walk never races with a change to it.
+    /// Once it runs out, slots past the end are counted off atomically. The Entity does not exist until
+    /// `flush_reserved`, which has to run before anything else changes the map
+    ///
+    /// Panics where `try_reserve_entity` would fail
+    pub fn reserve_entity(&self) -> Entity {
+        match self.try_reserve_entity() {
+            Ok(entity) => entity,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Like `reserve_entity`, but a fixed capacity map fails once its free list runs out instead of
+    /// going past the end
+    pub fn try_reserve_entity(&self) -> Result<Entity, InsertError> {
+        let mut slot = self.reserve_cursor.load(Ordering::Acquire);
+        while let Some(index) = decode_slot(slot) {
+            let (next_free, generational_index) = match self.items[index] {
+                EntityEntry::Free { next_free, generational_index } => (next_free, generational_index),
+                EntityEntry::Occupied { .. } | EntityEntry::Retired => panic!("corrupt free list"),
+            };
+            match self.reserve_cursor.compare_exchange_weak(
+                slot,
+                encode_slot(next_free),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Ok(Entity::new(index, generational_index)),
+                Err(current) => slot = current,
+            }
+        }
+
+        if self.fixed {
+            return Err(self.exhausted());
+        }
+        let offset = self.reserved_past_end.fetch_add(1, Ordering::Relaxed);
+        Ok(Entity::new(self.items.len() + offset, 0))
+    }
+
+    /// True if `reserve_entity` handed out anything since the last `flush_reserved`
+    pub fn has_reserved(&self) -> bool {
+        decode_slot(self.reserve_cursor.load(Ordering::Acquire)) != self.free_list_head
+            || self.reserved_past_end.load(Ordering::Acquire) > 0
+    }
+
+    /// Turns every reserved Entity into a real one, with the value `init` returns for it
+    ///
+    /// Entities are filled in the order they were reserved
+    pub fn flush_reserved(&mut self, mut init: impl FnMut(Entity) -> T) {
+        let cursor = decode_slot(*self.reserve_cursor.get_mut());
+        while self.free_list_head != cursor {
+            let index = self.free_list_head.expect("reserve cursor is not on the free list");
+            let (next_free, generational_index) = match self.items[index] {
+                EntityEntry::Free { next_free, generational_index } => (next_free, generational_index),
+                EntityEntry::Occupied { .. } | EntityEntry::Retired => panic!("corrupt free list"),
+            };
+            let value = init(Entity::new(index, generational_index));
+            self.items[index] = EntityEntry::Occupied { generational_index, value };
+            self.set_free_list_head(next_free);
+            self.len += 1;
+        }
+
+        for _ in 0..replace(self.reserved_past_end.get_mut(), 0) {
+            let entity = Entity::new(self.items.len(), 0);
+            let value = init(entity);
+            self.items.push(EntityEntry::Occupied {
+                generational_index: 0,
+                value,
+            });
+            self.len += 1;
+        }
+    }
+
+    /// Everything that touches the free list or the length of `items` would pull the rug out from
+    /// under outstanding reservations
+    fn assert_flushed(&mut self) {
+        assert!(
+            decode_slot(*self.reserve_cursor.get_mut()) == self.free_list_head && *self.reserved_past_end.get_mut() == 0,
+            "reserved entities have to be flushed before the entity map is changed"
+        );
+    }
+
+    /// Why a map with an empty free list cannot hand out another Entity
+    fn exhausted(&self) -> InsertError {
+        if self.retired > 0 && self.retired == self.capacity() {
+            InsertError::GenerationExhausted
+        } else if !self.quarantine.is_empty() {
+            InsertError::Quarantined
+        } else {
+            InsertError::CapacityExhausted
+        }
+    }
+
+    fn set_free_list_head(&mut self, head: Option<usize>) {
+        self.free_list_head = head;
+        *self.reserve_cursor.get_mut() = encode_slot(head);
+        if head.is_none() {
+            self.free_list_tail = None;
+        }
+    }
+
+    /// Puts a free slot at the front of the free list, it is handed out next
+    fn push_free_front(&mut self, index: usize) {
+        if let EntityEntry::Free { next_free, .. } = &mut self.items[index] {
+            *next_free = self.free_list_head;
+        }
+        if self.free_list_tail.is_none() {
+            self.free_list_tail = Some(index);
+        }
+        self.set_free_list_head(Some(index));
+    }
+
+    /// Puts a free slot at the back of the free list, it is handed out after everything already on it
+    fn push_free_back(&mut self, index: usize) {
+        if let EntityEntry::Free { next_free, .. } = &mut self.items[index] {
+            *next_free = None;
+        }
+        match self.free_list_tail {
+            Some(tail) => {
+                if let EntityEntry::Free { next_free, .. } = &mut self.items[tail] {
+                    *next_free = Some(index);
+                }
+                self.free_list_tail = Some(index);
+            }
+            None => {
+                self.free_list_tail = Some(index);
+                self.set_free_list_head(Some(index));
+            }
+        }
     }
 
     pub fn remove(&mut self, i: Entity) -> Option<T> {
+        self.assert_flushed();
         if i.index >= self.items.len() {
             return None;
         }
 
         match self.items[i.index] {
             EntityEntry::Occupied { generational_index, .. } if i.generational_index == generational_index => {
-                let entry = replace(&mut self.items[i.index], EntityEntry::Free { next_free: self.free_list_head });
-                self.generational_index += 1;
-                self.free_list_head = Some(i.index);
-                self.len -= 1;
+                Some(self.free_slot(i.index, generational_index))
+            }
+            _ => None,
+        }
+    }
+
+    /// Hands an occupied slot back to the free list as the RecyclePolicy says, returning the value
+    /// that lived there
+    ///
+    /// A slot that was on its last generation is retired instead
+    fn free_slot(&mut self, index: usize, generational_index: u32) -> T {
+        let entry = if generational_index >= self.max_generation {
+            self.retired += 1;
+            replace(&mut self.items[index], EntityEntry::Retired)
+        } else {
+            let entry = replace(&mut self.items[index], EntityEntry::Free {
+                next_free: None,
+                generational_index: generational_index + 1,
+            });
+            match self.policy {
+                RecyclePolicy::Lifo => self.push_free_front(index),
+                RecyclePolicy::Fifo | RecyclePolicy::Quarantine(0) => self.push_free_back(index),
+                RecyclePolicy::Quarantine(_) => self.quarantine.push_back((self.frame, index)),
+            }
+            entry
+        };
+        self.len -= 1;
+
+        match entry {
+            EntityEntry::Occupied { generational_index: _, value } => value,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Keeps only the entries for which `f` returns true, everything else is removed
+    pub fn retain<F: FnMut(Entity, &mut T) -> bool>(&mut self, mut f: F) {
+        self.assert_flushed();
+        for index in 0..self.items.len() {
+            let remove = match &mut self.items[index] {
+                EntityEntry::Occupied { generational_index, value } => {
+                    !f(Entity::new(index, *generational_index), value)
+                }
+                EntityEntry::Free { .. } | EntityEntry::Retired => false,
+            };
 
-                match entry {
-                    EntityEntry::Occupied { generational_index: _, value } => Some(value),
-                    _ => unreachable!(),
+            if remove {
+                if let EntityEntry::Occupied { generational_index, .. } = self.items[index] {
+                    self.free_slot(index, generational_index);
                 }
             }
-            _ => None,
         }
     }
 
+    /// Removes every entry, yielding them as it goes. Entries the iterator does not get to are still
+    /// removed when it is dropped
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        self.assert_flushed();
+        Drain {
+            map: self,
+            index: 0,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            len: self.len,
+            inner: self.items.iter().enumerate(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            len: self.len,
+            inner: self.items.iter_mut().enumerate(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of slots, retired ones included
     pub fn capacity(&self) -> usize {
         self.items.len()
     }
 
+    /// Number of slots that went through every generation and will never be handed out again
+    pub fn retired(&self) -> usize {
+        self.retired
+    }
+
     pub fn contains(&self, i: Entity) -> bool {
         self.get(i).is_some()
     }
@@ -177,7 +600,13 @@ impl<T> EntityMap<T> {
         }
     }
 
+    /// Panics for a fixed capacity map
     pub fn reserve(&mut self, additional_capacity: usize) {
+        assert!(!self.fixed, "a fixed capacity entity map cannot grow");
+        self.assert_flushed();
+        if additional_capacity == 0 {
+            return;
+        }
         let start = self.items.len();
         let end = self.items.len() + additional_capacity;
         let old_head = self.free_list_head;
@@ -186,13 +615,613 @@ impl<T> EntityMap<T> {
             if i == end - 1 {
                 EntityEntry::Free {
                     next_free: old_head,
+                    generational_index: 0,
                 }
             } else {
                 EntityEntry::Free {
                     next_free: Some(i + 1),
+                    generational_index: 0,
                 }
             }
         }));
-        self.free_list_head = Some(start);
+        if old_head.is_none() {
+            self.free_list_tail = Some(end - 1);
+        }
+        self.set_free_list_head(Some(start));
+    }
+}
+
+/// Borrowing iterator over the occupied slots of an EntityMap
+#[derive(Clone, Debug)]
+pub struct Iter<'a, T> {
+    len: usize,
+    inner: Enumerate<slice::Iter<'a, EntityEntry<T>>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (Entity, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (index, entry) in self.inner.by_ref() {
+            if let EntityEntry::Occupied { generational_index, value } = entry {
+                self.len -= 1;
+                return Some((Entity::new(index, *generational_index), value));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
+/// Mutably borrowing iterator over the occupied slots of an EntityMap
+#[derive(Debug)]
+pub struct IterMut<'a, T> {
+    len: usize,
+    inner: Enumerate<slice::IterMut<'a, EntityEntry<T>>>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = (Entity, &'a mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (index, entry) in self.inner.by_ref() {
+            if let EntityEntry::Occupied { generational_index, value } = entry {
+                self.len -= 1;
+                return Some((Entity::new(index, *generational_index), value));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+impl<'a, T> FusedIterator for IterMut<'a, T> {}
+
+/// Owning iterator over the occupied slots of an EntityMap
+#[derive(Clone, Debug)]
+pub struct IntoIter<T> {
+    len: usize,
+    inner: Enumerate<vec::IntoIter<EntityEntry<T>>>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = (Entity, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (index, entry) in self.inner.by_ref() {
+            if let EntityEntry::Occupied { generational_index, value } = entry {
+                self.len -= 1;
+                return Some((Entity::new(index, generational_index), value));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+impl<T> FusedIterator for IntoIter<T> {}
+
+/// Removes entries from an EntityMap as it walks it, freed slots go straight back on the free list
+#[derive(Debug)]
+pub struct Drain<'a, T> {
+    map: &'a mut EntityMap<T>,
+    index: usize,
+}
+
+impl<'a, T> Iterator for Drain<'a, T> {
+    type Item = (Entity, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.map.items.len() {
+            let index = self.index;
+            self.index += 1;
+
+            if let EntityEntry::Occupied { generational_index, .. } = self.map.items[index] {
+                let value = self.map.free_slot(index, generational_index);
+                return Some((Entity::new(index, generational_index), value));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.map.len, Some(self.map.len))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Drain<'a, T> {}
+impl<'a, T> FusedIterator for Drain<'a, T> {}
+
+impl<'a, T> Drop for Drain<'a, T> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+impl<T> IntoIterator for EntityMap<T> {
+    type Item = (Entity, T);
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            len: self.len,
+            inner: self.items.into_iter().enumerate(),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a EntityMap<T> {
+    type Item = (Entity, &'a T);
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut EntityMap<T> {
+    type Item = (Entity, &'a mut T);
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A map whose slots only get generations 0 through `max_generation`
+    fn narrow(capacity: usize, max_generation: u32) -> EntityMap<u32> {
+        let mut map = EntityMap::with_capcity(capacity);
+        map.max_generation = max_generation;
+        map
+    }
+
+    /// Inserts and removes in a single slot until it has been through `generations` generations
+    fn churn(map: &mut EntityMap<u32>, generations: u32) -> Vec<Entity> {
+        (0..generations)
+            .map(|i| {
+                let entity = map.insert(i);
+                assert_eq!(map.remove(entity), Some(i));
+                entity
+            })
+            .collect()
+    }
+
+    #[test]
+    fn slot_is_retired_after_its_last_generation() {
+        let mut map = narrow(1, 3);
+        let entities = churn(&mut map, 4);
+        assert_eq!(entities.iter().map(Entity::generation).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+        assert!(entities.iter().all(|entity| entity.index() == 0));
+        assert_eq!(map.retired(), 1);
+
+        // The retired slot is skipped, the map grows instead of handing out index 0 again
+        let entity = map.insert(7);
+        assert_eq!(entity.index(), 1);
+        assert_eq!(entity.generation(), 0);
+        assert_eq!(map.retired(), 1);
+    }
+
+    #[test]
+    fn stale_entities_stay_invalid_after_retirement() {
+        let mut map = narrow(1, 1);
+        let entities = churn(&mut map, 2);
+        let fresh = map.insert(5);
+        for &stale in &entities {
+            assert!(!map.contains(stale));
+            assert_eq!(map.get(stale), None);
+            assert_eq!(map.remove(stale), None);
+        }
+        assert_eq!(map.get(fresh), Some(&5));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn retired_slots_are_skipped_by_iteration_and_retain() {
+        let mut map = narrow(2, 0);
+        let a = map.insert(1);
+        let b = map.insert(2);
+        map.remove(a);
+        assert_eq!(map.retired(), 1);
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![(b, &2)]);
+
+        map.retain(|_, _| false);
+        assert_eq!(map.retired(), 2);
+        assert!(map.is_empty());
+        assert_eq!(map.drain().count(), 0);
+    }
+
+    #[test]
+    fn reservations_skip_retired_slots() {
+        let mut map = narrow(2, 0);
+        let a = map.insert(1);
+        map.remove(a);
+        let reserved = map.reserve_entity();
+        assert_eq!(reserved, Entity::new(1, 0));
+        map.flush_reserved(|_| 3);
+        assert_eq!(map.get(reserved), Some(&3));
+    }
+
+    #[test]
+    fn fixed_capacity_map_reports_generation_exhausted_once_every_slot_is_retired() {
+        let mut map = narrow(2, 1).fixed();
+        let live = map.insert(0);
+        churn(&mut map, 2);
+        assert_eq!(map.retired(), 1);
+        assert_eq!(map.try_insert(9), Err((InsertError::CapacityExhausted, 9)));
+
+        map.remove(live);
+        churn(&mut map, 1);
+        assert_eq!(map.retired(), 2);
+        assert_eq!(map.try_insert(9), Err((InsertError::GenerationExhausted, 9)));
+        assert_eq!(map.try_reserve_entity(), Err(InsertError::GenerationExhausted));
+    }
+
+    #[test]
+    fn fixed_capacity_map_without_retired_slots_reports_capacity_exhausted() {
+        let mut map = EntityMap::with_capcity(1).fixed();
+        map.insert(0);
+        assert_eq!(map.try_insert(1), Err((InsertError::CapacityExhausted, 1)));
+        assert_eq!(map.retired(), 0);
+    }
+
+    #[test]
+    fn quarantined_slots_are_reported_until_released() {
+        let mut map = narrow(3, 0).fixed();
+        map.set_recycle_policy(RecyclePolicy::Quarantine(1));
+        // Retired rather than quarantined, it was on its last generation
+        let retired = map.insert(0);
+        map.remove(retired);
+        map.max_generation = 1;
+        let held = map.insert(1);
+        map.insert(2);
+        map.remove(held);
+        assert_eq!((map.retired(), map.quarantined()), (1, 1));
+        assert_eq!(map.try_insert(3), Err((InsertError::Quarantined, 3)));
+        assert_eq!(map.try_reserve_entity(), Err(InsertError::Quarantined));
+
+        map.advance_frame();
+        assert_eq!(map.try_insert(3).map(|entity| entity.index()), Ok(held.index()));
+        assert_eq!(map.try_insert(4), Err((InsertError::CapacityExhausted, 4)));
+    }
+
+    #[test]
+    fn try_insert_never_grows_the_map() {
+        let mut map = EntityMap::with_capcity(1);
+        map.insert(0);
+        assert_eq!(map.try_insert(1), Err((InsertError::CapacityExhausted, 1)));
+        assert_eq!(map.capacity(), 1);
+        assert_eq!(map.insert(1).index(), 1);
+        assert_eq!(map.capacity(), 2);
+    }
+
+    #[test]
+    fn full_width_generations_still_retire() {
+        let mut map = EntityMap::with_capcity(1);
+        map.items[0] = EntityEntry::Free {
+            next_free: None,
+            generational_index: DANGLING_GENERATION - 1,
+        };
+        let entity = map.insert(1);
+        assert_eq!(entity.generation(), DANGLING_GENERATION - 1);
+        map.remove(entity);
+        assert_eq!(map.retired(), 1);
+        assert_eq!(map.insert(2).index(), 1);
+    }
+
+    /// Fills a map of exactly `capacity` slots, then frees them in index order
+    fn freed_in_order(capacity: usize, policy: RecyclePolicy) -> EntityMap<u32> {
+        let mut map = EntityMap::with_capcity(capacity);
+        map.set_recycle_policy(policy);
+        let entities: Vec<_> = (0..capacity as u32).map(|i| map.insert(i)).collect();
+        for entity in entities {
+            map.remove(entity);
+        }
+        map
+    }
+
+    fn reuse_order(map: &mut EntityMap<u32>, count: usize) -> Vec<usize> {
+        (0..count).map(|_| map.insert(0).index()).collect()
+    }
+
+    #[test]
+    fn lifo_reuses_the_last_freed_slot_first() {
+        let mut map = freed_in_order(3, RecyclePolicy::Lifo);
+        assert_eq!(reuse_order(&mut map, 3), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn fifo_reuses_the_first_freed_slot_first() {
+        let mut map = freed_in_order(3, RecyclePolicy::Fifo);
+        assert_eq!(reuse_order(&mut map, 3), vec![0, 1, 2]);
+        assert_eq!(map.capacity(), 3);
+
+        // Freed slots queue up behind the ones already waiting
+        let mut map = freed_in_order(3, RecyclePolicy::Fifo);
+        let first = map.insert(0);
+        map.remove(first);
+        assert_eq!(reuse_order(&mut map, 3), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn quarantine_holds_slots_back_for_the_given_number_of_frames() {
+        let mut map = freed_in_order(2, RecyclePolicy::Quarantine(2));
+        assert_eq!(map.quarantined(), 2);
+        // Growing from 2 to 4 slots, 3 is still free afterwards
+        assert_eq!(map.insert(0).index(), 2);
+
+        map.advance_frame();
+        assert_eq!(map.quarantined(), 2);
+        map.advance_frame();
+        assert_eq!(map.quarantined(), 0);
+        assert_eq!(reuse_order(&mut map, 3), vec![3, 0, 1]);
+    }
+
+    #[test]
+    fn quarantined_slots_reject_their_old_entities() {
+        let mut map = EntityMap::with_capcity(1);
+        map.set_recycle_policy(RecyclePolicy::Quarantine(1));
+        let old = map.insert(1);
+        map.remove(old);
+        assert!(!map.contains(old));
+        map.advance_frame();
+        let new = map.insert(2);
+        assert_eq!(new, Entity::new(old.index(), old.generation() + 1));
+        assert_eq!(map.get(old), None);
+    }
+
+    #[test]
+    fn leaving_quarantine_releases_held_slots() {
+        let mut map = freed_in_order(2, RecyclePolicy::Quarantine(10));
+        map.set_recycle_policy(RecyclePolicy::Lifo);
+        assert_eq!(map.quarantined(), 0);
+        assert_eq!(reuse_order(&mut map, 2), vec![0, 1]);
+    }
+
+    #[test]
+    fn reservations_follow_the_recycle_policy() {
+        let mut map = freed_in_order(3, RecyclePolicy::Fifo);
+        let reserved: Vec<_> = (0..4).map(|_| map.reserve_entity().index()).collect();
+        assert_eq!(reserved, vec![0, 1, 2, 3]);
+        map.flush_reserved(|entity| entity.index() as u32);
+        assert_eq!(map.len(), 4);
+
+        // The free list has to stay usable for appending once reservations emptied it
+        map.remove(Entity::new(2, 1));
+        map.remove(Entity::new(1, 1));
+        assert_eq!(reuse_order(&mut map, 2), vec![2, 1]);
+    }
+
+    #[test]
+    fn removing_only_advances_the_generation_of_that_slot() {
+        let mut map = EntityMap::with_capcity(2);
+        let a = map.insert(1);
+        let b = map.insert(2);
+        map.remove(a);
+        let c = map.insert(3);
+        assert_eq!(c.index(), a.index());
+        assert_eq!(c.generation(), a.generation() + 1);
+        assert_eq!(map.get(b), Some(&2));
+        assert_eq!(b.generation(), 0);
+    }
+
+    #[test]
+    fn stale_entities_miss_after_their_slot_is_reused() {
+        let mut map = EntityMap::with_capcity(1);
+        let old = map.insert(1);
+        map.remove(old);
+        let new = map.insert(2);
+        assert!(!map.contains(old));
+        assert_eq!(map.get(old), None);
+        assert_eq!(map.get_mut(old), None);
+        assert_eq!(map.remove(old), None);
+        assert_eq!(map.get(new), Some(&2));
+    }
+
+    /// Entities 0 to 4 holding 0 to 4, with 1 and 3 removed again
+    fn with_holes() -> (EntityMap<u32>, Vec<Entity>) {
+        let mut map = EntityMap::with_capcity(5);
+        let entities: Vec<_> = (0..5).map(|i| map.insert(i)).collect();
+        map.remove(entities[1]);
+        map.remove(entities[3]);
+        (map, entities)
+    }
+
+    #[test]
+    fn iterators_skip_free_slots() {
+        let (mut map, entities) = with_holes();
+        let iter = map.iter();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(
+            iter.collect::<Vec<_>>(),
+            vec![(entities[0], &0), (entities[2], &2), (entities[4], &4)]
+        );
+
+        for (_, value) in map.iter_mut() {
+            *value *= 10;
+        }
+        assert_eq!((&map).into_iter().map(|(_, value)| *value).collect::<Vec<_>>(), vec![0, 20, 40]);
+        assert_eq!(
+            map.into_iter().collect::<Vec<_>>(),
+            vec![(entities[0], 0), (entities[2], 20), (entities[4], 40)]
+        );
+    }
+
+    #[test]
+    fn retain_frees_what_it_rejects() {
+        let (mut map, entities) = with_holes();
+        map.retain(|_, value| *value != 2);
+        assert_eq!(map.len(), 2);
+        assert!(!map.contains(entities[2]));
+        assert!(map.contains(entities[0]) && map.contains(entities[4]));
+
+        // The rejected slot was freed, not just hidden
+        let reused = map.insert(7);
+        assert_eq!(reused.index(), entities[2].index());
+    }
+
+    #[test]
+    fn drain_empties_the_map_even_when_dropped_early() {
+        let (mut map, entities) = with_holes();
+        let mut drain = map.drain();
+        assert_eq!(drain.len(), 3);
+        assert_eq!(drain.next(), Some((entities[0], 0)));
+        drop(drain);
+
+        assert!(map.is_empty());
+        assert_eq!(map.iter().count(), 0);
+        assert!(entities.iter().all(|&entity| !map.contains(entity)));
+    }
+
+    #[test]
+    fn entity_bits_round_trip() {
+        let entity = Entity::new(0xDEAD, 0xBEEF);
+        assert_eq!(entity.to_bits(), 0x0000_BEEF_0000_DEAD);
+        assert_eq!(Entity::from_bits(entity.to_bits()), entity);
+
+        let largest = Entity::new(u32::MAX as usize, u32::MAX);
+        assert_eq!(Entity::from_bits(largest.to_bits()), largest);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    #[should_panic(expected = "does not fit in 32 bits")]
+    fn entity_bits_reject_wide_indices() {
+        Entity::new(u32::MAX as usize + 1, 0).to_bits();
+    }
+
+    #[test]
+    fn entities_order_by_index_then_generation() {
+        let mut entities = vec![Entity::new(1, 0), Entity::new(0, 5), Entity::new(1, 1), Entity::new(0, 0)];
+        entities.sort();
+        assert_eq!(entities, vec![Entity::new(0, 0), Entity::new(0, 5), Entity::new(1, 0), Entity::new(1, 1)]);
+        assert_eq!(Entity::new(3, 2).to_string(), "3v2");
+    }
+
+    #[test]
+    fn concurrent_reservations_hand_out_unique_entities() {
+        let mut map = EntityMap::with_capcity(16);
+        let freed: Vec<Entity> = (0..16).map(|i| map.insert(i)).collect();
+        for &entity in &freed {
+            map.remove(entity);
+        }
+
+        let reserved: Vec<Entity> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| (0..50).map(|_| map.reserve_entity()).collect::<Vec<_>>()))
+                .collect();
+            workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect()
+        });
+        let mut unique = reserved.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), 200);
+        assert_eq!(reserved.iter().filter(|entity| entity.generation() == 1).count(), 16);
+        assert!(freed.iter().all(|entity| !reserved.contains(entity)));
+
+        map.flush_reserved(|entity| entity.index() as u32);
+        assert_eq!(map.len(), 200);
+        assert!(reserved.iter().all(|&entity| map.get(entity) == Some(&(entity.index() as u32))));
+    }
+
+    #[test]
+    fn flush_fills_freed_slots_and_slots_past_the_end() {
+        let mut map = EntityMap::with_capcity(2);
+        let a = map.insert(0);
+        let b = map.insert(1);
+        map.remove(a);
+        map.remove(b);
+
+        let reserved: Vec<Entity> = (0..3).map(|_| map.reserve_entity()).collect();
+        assert!(map.has_reserved());
+        assert!(reserved.iter().all(|&entity| !map.contains(entity)));
+        assert_eq!(reserved[2], Entity::new(2, 0));
+        assert_eq!(map.capacity(), 2);
+
+        let mut order = Vec::new();
+        map.flush_reserved(|entity| {
+            order.push(entity);
+            10 + order.len() as u32
+        });
+        assert_eq!(order, reserved);
+        assert!(!map.has_reserved());
+        assert_eq!(map.capacity(), 3);
+        assert_eq!(map.get(reserved[0]), Some(&11));
+        assert_eq!(map.get(reserved[2]), Some(&13));
+        assert!(!map.contains(a) && !map.contains(b));
+    }
+
+    #[test]
+    #[should_panic(expected = "reserved entities have to be flushed")]
+    fn changes_before_flushing_panic() {
+        let mut map = EntityMap::with_capcity(1);
+        map.reserve_entity();
+        map.insert(0);
+    }
+
+    #[test]
+    fn fixed_capacity_maps_reuse_slots_without_growing() {
+        let mut map = EntityMap::with_capcity(2).fixed();
+        assert!(map.is_fixed());
+        let a = map.insert("a".to_string());
+        map.insert("b".to_string());
+        assert_eq!(map.try_insert("c".to_string()), Err((InsertError::CapacityExhausted, "c".to_string())));
+
+        map.remove(a);
+        let c = map.try_insert("c".to_string()).unwrap();
+        assert_eq!(c.index(), a.index());
+        assert_eq!(map.get(c).map(String::as_str), Some("c"));
+        assert_eq!(map.capacity(), 2);
+    }
+
+    #[test]
+    fn zero_fixed_capacity_holds_nothing() {
+        let mut map = EntityMap::with_capcity(0).fixed();
+        assert_eq!(map.capacity(), 0);
+        assert_eq!(map.try_insert(1), Err((InsertError::CapacityExhausted, 1)));
+        assert_eq!(map.try_reserve_entity(), Err(InsertError::CapacityExhausted));
+        assert!(!map.has_reserved());
+        assert_eq!(map.iter().count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "entity map is at its fixed capacity")]
+    fn inserting_into_a_full_fixed_capacity_map_panics() {
+        let mut map = EntityMap::with_capcity(1).fixed();
+        map.insert(0);
+        map.insert(1);
+    }
+
+    #[test]
+    fn slow_path_grows_the_map() {
+        let mut map = EntityMap::with_capcity(1);
+        map.insert(0);
+        let entity = map.insert_slow_path(1);
+        assert_eq!(map.capacity(), 2);
+        assert_eq!(map.get(entity), Some(&1));
+    }
+
+    #[test]
+    #[should_panic(expected = "a fixed capacity entity map cannot grow")]
+    fn slow_path_panics_for_fixed_capacity_maps() {
+        let mut map = EntityMap::with_capcity(1).fixed();
+        map.insert_slow_path(0);
     }
 }
@@ -0,0 +1,400 @@
+use std::iter::Chain;
+use std::marker::PhantomData;
+use std::slice;
+
+use super::resource::{ Res, ResMut };
+use super::system::{ SystemMeta, SystemParam };
+use super::world::World;
+
+/// Something that happened during a frame, e.g. a collision or a key press
+pub trait Event: Send + Sync + 'static {}
+
+impl<T: Send + Sync + 'static> Event for T {}
+
+#[derive(Debug)]
+struct EventSequence<E: Event> {
+    events: Vec<E>,
+    /// Count of every event sent before the first one in `events`
+    start_event_count: usize,
+}
+
+impl<E: Event> EventSequence<E> {
+    fn new() -> EventSequence<E> {
+        EventSequence {
+            events: Vec::new(),
+            start_event_count: 0,
+        }
+    }
+
+    /// The events a reader that has seen `event_count` events has not seen yet
+    fn unread(&self, event_count: usize) -> &[E] {
+        let skip = event_count.saturating_sub(self.start_event_count).min(self.events.len());
+        &self.events[skip..]
+    }
+}
+
+/// Double buffered channel of events, stored in the World as a Resource
+///
+/// Events are kept for two calls to `update`, normally two frames, so every System gets to see them
+/// no matter whether it runs before or after the one sending them
+#[derive(Debug)]
+pub struct Events<E: Event> {
+    /// Events sent before the last `update`
+    previous: EventSequence<E>,
+    /// Events sent since the last `update`
+    current: EventSequence<E>,
+    event_count: usize,
+}
+
+impl<E: Event> Default for Events<E> {
+    fn default() -> Events<E> {
+        Events {
+            previous: EventSequence::new(),
+            current: EventSequence::new(),
+            event_count: 0,
+        }
+    }
+}
+
+impl<E: Event> Events<E> {
+    pub fn new() -> Events<E> {
+        Events::default()
+    }
+
+    pub fn send(&mut self, event: E) {
+        self.current.events.push(event);
+        self.event_count += 1;
+    }
+
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = E>) {
+        for event in events {
+            self.send(event);
+        }
+    }
+
+    /// Drops the events sent before the last update, the ones sent since are kept for one more update
+    pub fn update(&mut self) {
+        std::mem::swap(&mut self.previous, &mut self.current);
+        self.current.events.clear();
+        self.current.start_event_count = self.event_count;
+    }
+
+    /// Runs `update` once per frame, added to a Schedule by `Schedule::add_event`
+    pub fn update_system(events: Option<ResMut<Events<E>>>) {
+        if let Some(mut events) = events {
+            events.update();
+        }
+    }
+
+    /// Drops every buffered event, readers that had not seen them are told they missed them
+    pub fn clear(&mut self) {
+        self.drain().for_each(drop);
+    }
+
+    /// Takes every buffered event out, oldest first
+    pub fn drain(&mut self) -> impl Iterator<Item = E> + '_ {
+        self.previous.start_event_count = self.event_count;
+        self.current.start_event_count = self.event_count;
+        self.previous.events.drain(..).chain(self.current.events.drain(..))
+    }
+
+    /// Number of buffered events
+    pub fn len(&self) -> usize {
+        self.previous.events.len() + self.current.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of events ever sent, the next event gets this as its id
+    pub fn event_count(&self) -> usize {
+        self.event_count
+    }
+
+    /// Id of the oldest event still buffered
+    pub fn oldest_event_count(&self) -> usize {
+        self.previous.start_event_count.min(self.current.start_event_count)
+    }
+
+    /// A reader that sees every event still buffered
+    pub fn get_reader(&self) -> ManualEventReader<E> {
+        ManualEventReader {
+            last_event_count: self.oldest_event_count(),
+            marker: PhantomData,
+        }
+    }
+
+    /// A reader that only sees events sent from now on
+    pub fn get_reader_current(&self) -> ManualEventReader<E> {
+        ManualEventReader {
+            last_event_count: self.event_count,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Cursor into an Events<E>, every reader keeps its own and so sees every event once
+#[derive(Debug)]
+pub struct ManualEventReader<E: Event> {
+    last_event_count: usize,
+    marker: PhantomData<fn() -> E>,
+}
+
+impl<E: Event> Default for ManualEventReader<E> {
+    fn default() -> ManualEventReader<E> {
+        ManualEventReader {
+            last_event_count: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<E: Event> ManualEventReader<E> {
+    /// Reads every event this reader has not seen yet, oldest first
+    ///
+    /// Events dropped by `update` or `clear` before this reader got to them are skipped, `missed` on the
+    /// returned iterator says how many there were
+    pub fn read<'a>(&mut self, events: &'a Events<E>) -> EventIter<'a, E> {
+        let oldest = events.oldest_event_count();
+        let missed = oldest.saturating_sub(self.last_event_count);
+        let start = self.last_event_count.max(oldest);
+        self.last_event_count = events.event_count;
+
+        EventIter {
+            iter: events.previous.unread(start).iter().chain(events.current.unread(start).iter()),
+            missed,
+        }
+    }
+
+    /// Number of events dropped before this reader could see them
+    pub fn missed_events(&self, events: &Events<E>) -> usize {
+        events.oldest_event_count().saturating_sub(self.last_event_count)
+    }
+
+    /// Number of buffered events this reader has not seen yet
+    pub fn len(&self, events: &Events<E>) -> usize {
+        let start = self.last_event_count.max(events.oldest_event_count());
+        events.event_count - start
+    }
+
+    pub fn is_empty(&self, events: &Events<E>) -> bool {
+        self.len(events) == 0
+    }
+
+    /// Marks every buffered event as seen
+    pub fn clear(&mut self, events: &Events<E>) {
+        self.last_event_count = events.event_count;
+    }
+}
+
+/// Iterator over the events a reader has not seen yet
+pub struct EventIter<'a, E: Event> {
+    iter: Chain<slice::Iter<'a, E>, slice::Iter<'a, E>>,
+    missed: usize,
+}
+
+impl<'a, E: Event> EventIter<'a, E> {
+    /// Number of events that were dropped before the reader got to them
+    pub fn missed(&self) -> usize {
+        self.missed
+    }
+}
+
+impl<'a, E: Event> Iterator for EventIter<'a, E> {
+    type Item = &'a E;
+
+    fn next(&mut self) -> Option<&'a E> {
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, E: Event> ExactSizeIterator for EventIter<'a, E> {}
+
+/// System parameter sending events of type E
+pub struct EventWriter<'w, E: Event> {
+    events: ResMut<'w, Events<E>>,
+}
+
+impl<'w, E: Event> EventWriter<'w, E> {
+    pub fn send(&mut self, event: E) {
+        self.events.send(event);
+    }
+
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = E>) {
+        self.events.send_batch(events);
+    }
+}
+
+/// System parameter reading events of type E, each System has its own cursor
+pub struct EventReader<'w, 's, E: Event> {
+    reader: &'s mut ManualEventReader<E>,
+    events: Res<'w, Events<E>>,
+}
+
+impl<'w, 's, E: Event> EventReader<'w, 's, E> {
+    /// Reads every event this System has not seen yet, see `ManualEventReader::read`
+    pub fn read(&mut self) -> EventIter<'_, E> {
+        self.reader.read(&self.events)
+    }
+
+    pub fn missed_events(&self) -> usize {
+        self.reader.missed_events(&self.events)
+    }
+
+    pub fn len(&self) -> usize {
+        self.reader.len(&self.events)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reader.is_empty(&self.events)
+    }
+
+    pub fn clear(&mut self) {
+        self.reader.clear(&self.events);
+    }
+}
+
+/// Events<E> is created on demand, so Systems can send or read events nobody else set up
+fn init_events<E: Event>(world: &mut World) {
+    if !world.contains_resource::<Events<E>>() {
+        world.insert_resource(Events::<E>::new());
+    }
+}
+
+unsafe impl<'a, E: Event> SystemParam for EventWriter<'a, E> {
+    type State = ();
+    type Item<'w, 's> = EventWriter<'w, E>;
+
+    fn init_state(world: &mut World, meta: &mut SystemMeta) {
+        init_events::<E>(world);
+        ResMut::<Events<E>>::init_state(world, meta);
+    }
+
+    unsafe fn get_param<'w, 's>(state: &'s mut (), meta: &SystemMeta, world: &'w World) -> Self::Item<'w, 's> {
+        EventWriter {
+            events: ResMut::<Events<E>>::get_param(state, meta, world),
+        }
+    }
+}
+
+unsafe impl<'a, 'b, E: Event> SystemParam for EventReader<'a, 'b, E> {
+    type State = ManualEventReader<E>;
+    type Item<'w, 's> = EventReader<'w, 's, E>;
+
+    fn init_state(world: &mut World, meta: &mut SystemMeta) -> ManualEventReader<E> {
+        init_events::<E>(world);
+        Res::<Events<E>>::init_state(world, meta);
+        world.resource::<Events<E>>().map(Events::get_reader).unwrap_or_default()
+    }
+
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut ManualEventReader<E>,
+        meta: &SystemMeta,
+        world: &'w World,
+    ) -> Self::Item<'w, 's> {
+        EventReader {
+            reader: state,
+            events: Res::<Events<E>>::get_param(&mut (), meta, world),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::entity::system::{ IntoSystem, System };
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Hit(u32);
+
+    fn read(reader: &mut ManualEventReader<Hit>, events: &Events<Hit>) -> (Vec<u32>, usize) {
+        let iter = reader.read(events);
+        let missed = iter.missed();
+        (iter.map(|hit| hit.0).collect(), missed)
+    }
+
+    #[test]
+    fn events_live_for_two_updates() {
+        let mut events = Events::new();
+        let mut reader = events.get_reader();
+        events.send(Hit(1));
+        events.update();
+        events.send(Hit(2));
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(read(&mut reader, &events), (vec![1, 2], 0));
+        assert_eq!(read(&mut reader, &events), (vec![], 0));
+
+        let mut late = events.get_reader();
+        events.update();
+        assert_eq!(events.len(), 1);
+        assert_eq!(read(&mut late, &events), (vec![2], 1));
+        events.update();
+        assert!(events.is_empty());
+        assert_eq!(events.event_count(), 2);
+    }
+
+    #[test]
+    fn readers_count_missed_events() {
+        let mut events = Events::new();
+        let mut reader = events.get_reader();
+        events.send_batch([Hit(1), Hit(2)]);
+        events.update();
+        events.send(Hit(3));
+        events.update();
+        events.send(Hit(4));
+
+        assert_eq!(reader.missed_events(&events), 2);
+        assert_eq!(reader.len(&events), 2);
+        assert_eq!(read(&mut reader, &events), (vec![3, 4], 2));
+        assert_eq!(reader.missed_events(&events), 0);
+        assert!(reader.is_empty(&events));
+
+        let mut current = events.get_reader_current();
+        events.send(Hit(5));
+        events.clear();
+        assert_eq!(current.missed_events(&events), 1);
+        assert_eq!(read(&mut current, &events), (vec![], 1));
+    }
+
+    #[test]
+    fn drain_takes_events_oldest_first() {
+        let mut events = Events::new();
+        events.send(Hit(1));
+        events.update();
+        events.send(Hit(2));
+        let mut reader = events.get_reader();
+
+        assert_eq!(events.drain().map(|hit| hit.0).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(events.is_empty());
+        assert_eq!(events.oldest_event_count(), 2);
+        assert_eq!(read(&mut reader, &events), (vec![], 2));
+    }
+
+    #[test]
+    fn systems_keep_their_own_cursor() {
+        let mut world = World::new();
+        let mut writer = (|mut writer: EventWriter<Hit>| writer.send_batch([Hit(1), Hit(2)])).into_system();
+        let mut reader = (|mut reader: EventReader<Hit>, mut seen: ResMut<Vec<u32>>| {
+            seen.extend(reader.read().map(|hit| hit.0))
+        })
+        .into_system();
+        world.insert_resource(Vec::<u32>::new());
+        writer.initialize(&mut world);
+        reader.initialize(&mut world);
+
+        writer.run(&mut world);
+        reader.run(&mut world);
+        reader.run(&mut world);
+        world.resource_mut::<Events<Hit>>().unwrap().update();
+        writer.run(&mut world);
+        reader.run(&mut world);
+
+        assert_eq!(world.resource::<Vec<u32>>(), Some(&vec![1, 2, 1, 2]));
+    }
+}
@@ -0,0 +1,356 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::mem;
+use std::panic::{ self, AssertUnwindSafe };
+use std::sync::mpsc;
+use std::sync::{ Arc, Mutex };
+use std::thread;
+
+use super::system::System;
+use super::world::World;
+
+/// How a Stage runs its Systems
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExecutorKind {
+    /// One after the other on the calling thread, handy for deterministic debugging
+    SingleThreaded,
+    /// Systems whose access does not conflict run at the same time on worker threads
+    #[default]
+    MultiThreaded,
+}
+
+/// Which Systems have to finish before others may start
+///
+/// Built from the sequential run order: a System waits on every earlier System it is explicitly
+/// ordered after or whose access conflicts with its own. Anything it does not wait on cannot observe
+/// it, so running the graph gives the same results as running the order one by one
+#[derive(Clone, Debug, Default)]
+pub struct SystemGraph {
+    dependents: Vec<Vec<usize>>,
+    dependency_counts: Vec<usize>,
+}
+
+impl SystemGraph {
+    /// `order` is the sequential run order, `dependencies[i]` the Systems explicitly ordered before `i`
+    pub fn new(systems: &[&dyn System], order: &[usize], dependencies: &[Vec<usize>]) -> SystemGraph {
+        let mut dependents = vec![Vec::new(); systems.len()];
+        let mut dependency_counts = vec![0; systems.len()];
+
+        for (position, &later) in order.iter().enumerate() {
+            for &earlier in order[..position].iter() {
+                let conflicts = !systems[earlier].access().is_compatible(systems[later].access());
+                if conflicts || dependencies[later].contains(&earlier) {
+                    dependents[earlier].push(later);
+                    dependency_counts[later] += 1;
+                }
+            }
+        }
+
+        SystemGraph {
+            dependents,
+            dependency_counts,
+        }
+    }
+
+    pub fn dependents(&self, system: usize) -> &[usize] {
+        &self.dependents[system]
+    }
+
+    pub fn dependency_count(&self, system: usize) -> usize {
+        self.dependency_counts[system]
+    }
+}
+
+/// Runs the Systems one by one in `order`
+pub fn run_single_threaded(systems: &mut [&mut dyn System], order: &[usize], world: &World) {
+    for &index in order {
+        // Only one System runs at a time
+        unsafe { systems[index].run_unsafe(world) };
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Worker threads that stay alive between runs so running a Stage does not spawn threads every frame
+///
+/// A Schedule shares one pool between all of its stages, the threads are joined when it is dropped
+pub struct ThreadPool {
+    jobs: Option<mpsc::Sender<Job>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    pub fn new(threads: usize) -> ThreadPool {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("ecs-worker-{}", i))
+                    .spawn(move || loop {
+                        let job = receiver.lock().expect("job queue poisoned").recv();
+                        match job {
+                            Ok(job) => job(),
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("failed to spawn an executor worker thread")
+            })
+            .collect();
+
+        ThreadPool {
+            jobs: Some(sender),
+            workers,
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    fn spawn(&self, job: Job) {
+        self.jobs
+            .as_ref()
+            .expect("thread pool is shutting down")
+            .send(job)
+            .expect("executor worker threads stopped");
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // Closing the queue lets every worker fall out of its loop
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Lets a pointer be handed to a worker, the executor makes sure whatever it points at outlives the job
+struct SendPtr<T: ?Sized>(*mut T);
+
+unsafe impl<T: ?Sized> Send for SendPtr<T> {}
+
+/// Dispatches Systems onto the pool as soon as everything they wait on has finished, with at most
+/// `threads` of them running at a time
+///
+/// Does not return before every dispatched System has finished. A panicking System stops anything new
+/// from being dispatched, the panic is resumed on the calling thread once the rest have wound down
+pub fn run_multi_threaded(
+    systems: &mut [&mut dyn System],
+    graph: &SystemGraph,
+    world: &World,
+    pool: &ThreadPool,
+    threads: usize,
+) {
+    let count = systems.len();
+    if count == 0 {
+        return;
+    }
+
+    let mut remaining: Vec<usize> = (0..count).map(|i| graph.dependency_count(i)).collect();
+    let mut ready: VecDeque<usize> = (0..count).filter(|&i| remaining[i] == 0).collect();
+    let (done_sender, done_receiver) = mpsc::channel::<(usize, Result<(), Box<dyn Any + Send>>)>();
+    let world = world as *const World as *mut World;
+    let mut failure = None;
+    let mut in_flight = 0;
+    let mut finished = 0;
+
+    loop {
+        while failure.is_none() && in_flight < threads.max(1) {
+            let index = match ready.pop_front() {
+                Some(index) => index,
+                None => break,
+            };
+            // The borrows are erased to 'static for the trip through the pool, which is sound
+            // because nothing returns from here before every dispatched job has reported back
+            let system: *mut (dyn System + '_) = &mut *systems[index];
+            let system = SendPtr(unsafe { mem::transmute::<*mut (dyn System + '_), *mut (dyn System + 'static)>(system) });
+            let world = SendPtr(world);
+            let done_sender = done_sender.clone();
+            pool.spawn(Box::new(move || {
+                // The graph only lets Systems with compatible access overlap and each System is only
+                // ever dispatched once
+                let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe { (*system.0).run_unsafe(&*world.0) }));
+                let _ = done_sender.send((index, result));
+            }));
+            in_flight += 1;
+        }
+
+        if in_flight == 0 {
+            break;
+        }
+        let (index, result) = done_receiver.recv().expect("the executor holds a sender itself");
+        in_flight -= 1;
+        finished += 1;
+
+        match result {
+            Err(payload) => {
+                failure.get_or_insert(payload);
+            }
+            Ok(()) if failure.is_none() => {
+                for &dependent in graph.dependents(index) {
+                    remaining[dependent] -= 1;
+                    if remaining[dependent] == 0 {
+                        ready.push_back(dependent);
+                    }
+                }
+            }
+            Ok(()) => {}
+        }
+    }
+    debug_assert!(failure.is_some() || finished == count, "system graph left systems unrun");
+
+    if let Some(payload) = failure {
+        panic::resume_unwind(payload);
+    }
+}
+
+/// Number of worker threads to use when none is configured
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map(usize::from).unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::modules::entity::schedule::{ IntoSystemDescriptor, Stage };
+    use crate::modules::entity::system::Query;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct A(i64);
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct B(i64);
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct C(i64);
+
+    /// Systems with overlapping and disjoint access, some explicitly ordered, so the outcome depends
+    /// on the executor getting the order right
+    fn stage(executor: ExecutorKind) -> Stage {
+        let mut stage = Stage::new();
+        stage
+            .set_executor(executor)
+            .add_system((|mut query: Query<&mut A>| {
+                for (_, mut a) in query.iter_mut() {
+                    a.0 = a.0 * 3 + 1;
+                }
+            }).label("a"))
+            .add_system(|mut query: Query<(&mut B, &A)>| {
+                for (_, (mut b, a)) in query.iter_mut() {
+                    b.0 += a.0;
+                }
+            })
+            .add_system((|mut query: Query<(&mut C, &B)>| {
+                for (_, (mut c, b)) in query.iter_mut() {
+                    c.0 = c.0 * 2 - b.0;
+                }
+            }).after("a"))
+            .add_system(|mut query: Query<&mut A>| {
+                for (_, mut a) in query.iter_mut() {
+                    a.0 %= 1_000_003;
+                }
+            })
+            .add_system(|mut query: Query<&mut C>| {
+                for (_, mut c) in query.iter_mut() {
+                    c.0 %= 999_983;
+                }
+            });
+        stage
+    }
+
+    fn run(executor: ExecutorKind) -> Vec<(A, B, C)> {
+        let mut world = World::new();
+        for i in 0..50 {
+            world.spawn((A(i), B(i * 7), C(-i)));
+        }
+        let mut stage = stage(executor);
+        for _ in 0..20 {
+            stage.run("test", &mut world).unwrap();
+        }
+
+        let mut query = world.query::<(&A, &B, &C)>();
+        query.iter(&world).map(|(_, (a, b, c))| (*a, *b, *c)).collect()
+    }
+
+    #[test]
+    fn multi_threaded_runs_match_single_threaded_runs() {
+        let expected = run(ExecutorKind::SingleThreaded);
+        for _ in 0..10 {
+            assert_eq!(run(ExecutorKind::MultiThreaded), expected);
+        }
+    }
+
+    #[test]
+    fn graph_only_orders_conflicting_systems() {
+        let mut world = World::new();
+        let mut stage = stage(ExecutorKind::MultiThreaded);
+        stage.initialize("test", &mut world).unwrap();
+        let systems: Vec<&dyn System> = stage.systems().iter().map(|descriptor| descriptor.system()).collect();
+        let graph = SystemGraph::new(&systems, stage.order(), &stage.dependencies());
+
+        assert_eq!(graph.dependency_count(0), 0);
+        // The second A writer and the B writer conflict with the first, the C writer is ordered
+        // after it explicitly
+        assert_eq!(graph.dependents(0), &[1, 2, 3]);
+        // Reading A has to finish before A is written again, B has to be written before C reads it
+        assert_eq!(graph.dependents(1), &[2, 3]);
+        assert_eq!(graph.dependents(2), &[4]);
+        assert_eq!(graph.dependency_count(4), 1);
+    }
+
+    #[test]
+    fn worker_threads_are_reused_between_runs() {
+        let names = Arc::new(Mutex::new(HashSet::new()));
+        let mut stage = Stage::new();
+        for _ in 0..4 {
+            let names = names.clone();
+            stage.add_system(move |_: Query<&A>| {
+                let name = thread::current().name().map(str::to_string);
+                names.lock().unwrap().insert(name);
+            });
+        }
+        stage.set_thread_pool(Arc::new(ThreadPool::new(2)));
+
+        let mut world = World::new();
+        for _ in 0..50 {
+            stage.run("test", &mut world).unwrap();
+        }
+        let names = names.lock().unwrap();
+        assert!(names.len() <= 2);
+        assert!(names.iter().all(|name| name.as_deref().is_some_and(|name| name.starts_with("ecs-worker-"))));
+    }
+
+    #[test]
+    #[should_panic(expected = "system failed")]
+    fn panics_are_resumed_on_the_caller() {
+        let mut world = World::new();
+        let mut stage = Stage::new();
+        stage
+            .add_system(|_: Query<&A>| {})
+            .add_system(|_: Query<&B>| panic!("system failed"))
+            .add_system(|_: Query<&C>| {});
+        stage.run("test", &mut world).unwrap();
+    }
+
+    #[test]
+    fn pool_survives_a_panicking_system() {
+        let pool = Arc::new(ThreadPool::new(1));
+        let mut world = World::new();
+        let mut failing = Stage::new();
+        failing.set_thread_pool(pool.clone()).add_system(|_: Query<&A>| panic!("system failed"));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| failing.run("test", &mut world)));
+        assert!(result.is_err());
+
+        let ran = Arc::new(Mutex::new(false));
+        let flag = ran.clone();
+        let mut stage = Stage::new();
+        stage.set_thread_pool(pool).add_system(move |_: Query<&A>| *flag.lock().unwrap() = true);
+        stage.run("test", &mut world).unwrap();
+        assert!(*ran.lock().unwrap());
+    }
+}
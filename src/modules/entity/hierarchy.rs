@@ -0,0 +1,420 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::Deref;
+
+use super::command::{ Commands, EntityCommands };
+use super::entity_map::Entity;
+use super::observer::Trigger;
+use super::reflect::{ FieldInfo, Reflect };
+use super::value::{ Persist, Value, ValueError };
+use super::world::World;
+
+/// Points at the Entity this one hangs off of
+///
+/// Hooks keep it in sync with the parent's Children however either side is changed, but only the
+/// hierarchy methods on World check for cycles
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Parent(Entity);
+
+impl Parent {
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+/// Every Entity whose Parent is this one, in the order they were attached
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Children(Vec<Entity>);
+
+impl Deref for Children {
+    type Target = [Entity];
+
+    fn deref(&self) -> &[Entity] {
+        &self.0
+    }
+}
+
+impl Persist for Parent {
+    fn to_value(&self) -> Value {
+        self.0.to_value()
+    }
+
+    fn from_value(value: &Value) -> Result<Parent, ValueError> {
+        Entity::from_value(value).map(Parent)
+    }
+}
+
+impl Persist for Children {
+    fn to_value(&self) -> Value {
+        self.0.to_value()
+    }
+
+    fn from_value(value: &Value) -> Result<Children, ValueError> {
+        Vec::from_value(value).map(Children)
+    }
+}
+
+/// Read-only, reflection setting one side of the relationship would leave the other one stale
+impl Reflect for Parent {
+    fn fields() -> &'static [FieldInfo] {
+        &[]
+    }
+
+    fn read_only() -> bool {
+        true
+    }
+}
+
+/// Read-only, see Parent
+impl Reflect for Children {
+    fn fields() -> &'static [FieldInfo] {
+        &[]
+    }
+
+    fn read_only() -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HierarchyError {
+    /// The Entity was never spawned or has been despawned
+    NoSuchEntity(Entity),
+    /// `attach` was used on an Entity that already has a parent, use `reparent` to move it
+    AlreadyAttached { child: Entity, parent: Entity },
+    /// Making `parent` the parent of `child` would make `child` its own ancestor
+    Cycle { child: Entity, parent: Entity },
+    /// The child's Parent points at an Entity that has since been despawned
+    DanglingParent { child: Entity, parent: Entity },
+}
+
+impl fmt::Display for HierarchyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HierarchyError::NoSuchEntity(entity) => write!(f, "{} does not exist", entity),
+            HierarchyError::AlreadyAttached { child, parent } => {
+                write!(f, "{} is already attached to {}", child, parent)
+            }
+            HierarchyError::Cycle { child, parent } => {
+                write!(f, "attaching {} to {} would make it its own ancestor", child, parent)
+            }
+            HierarchyError::DanglingParent { child, parent } => {
+                write!(f, "the parent of {}, {}, has been despawned", child, parent)
+            }
+        }
+    }
+}
+
+impl Error for HierarchyError {}
+
+/// Registers the hooks that keep Parent and Children in sync, called by `World::new`
+pub fn register_hierarchy_hooks(world: &mut World) {
+    world.component_hooks::<Parent>().on_insert(parent_inserted).on_replace(parent_replaced);
+    world.component_hooks::<Children>().on_insert(children_inserted).on_replace(children_replaced);
+}
+
+/// Lists the child in its new parent's Children, unless that parent is gone
+fn parent_inserted(_: &World, trigger: Trigger, commands: &mut Commands) {
+    let child = trigger.entity;
+    commands.add(move |world| {
+        if let Some(&Parent(parent)) = world.get::<Parent>(child) {
+            if world.contains(parent) && !world.children(parent).contains(&child) {
+                match world.get_mut::<Children>(parent) {
+                    Some(mut children) => children.0.push(child),
+                    None => {
+                        world.insert(parent, Children(vec![child]));
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Drops the child from its old parent's Children, unless the new Parent points at the same one
+fn parent_replaced(world: &World, trigger: Trigger, commands: &mut Commands) {
+    let child = trigger.entity;
+    let Parent(parent) = *world.get::<Parent>(child).unwrap();
+    commands.add(move |world| {
+        if world.get::<Parent>(child) != Some(&Parent(parent)) {
+            world.remove_child(parent, child);
+        }
+    });
+}
+
+/// Points every listed child at the Entity, moving it away from whatever parent it had
+fn children_inserted(world: &World, trigger: Trigger, commands: &mut Commands) {
+    let parent = trigger.entity;
+    let children = world.children(parent).to_vec();
+    commands.add(move |world| {
+        for child in children {
+            if world.contains(child) && world.get::<Parent>(child) != Some(&Parent(parent)) {
+                world.insert(child, Parent(parent));
+            }
+        }
+    });
+}
+
+/// Turns the children that are no longer listed into roots
+fn children_replaced(world: &World, trigger: Trigger, commands: &mut Commands) {
+    let parent = trigger.entity;
+    let children = world.children(parent).to_vec();
+    commands.add(move |world| {
+        for child in children {
+            if world.get::<Parent>(child) == Some(&Parent(parent)) && !world.children(parent).contains(&child) {
+                world.remove::<Parent>(child);
+            }
+        }
+    });
+}
+
+impl World {
+    /// Makes `parent` the parent of `child`, which must not have a parent yet
+    pub fn attach(&mut self, child: Entity, parent: Entity) -> Result<(), HierarchyError> {
+        if let Some(Parent(current)) = self.get::<Parent>(child).copied() {
+            return Err(HierarchyError::AlreadyAttached { child, parent: current });
+        }
+        self.reparent(child, parent)
+    }
+
+    /// Makes `parent` the parent of `child`, detaching it from its current parent first
+    ///
+    /// Fails without changing anything if either Entity is gone or `parent` is `child` or one of its
+    /// descendants
+    pub fn reparent(&mut self, child: Entity, parent: Entity) -> Result<(), HierarchyError> {
+        for entity in [child, parent] {
+            if !self.contains(entity) {
+                return Err(HierarchyError::NoSuchEntity(entity));
+            }
+        }
+        if self.is_ancestor_or_self(child, parent) {
+            return Err(HierarchyError::Cycle { child, parent });
+        }
+
+        // The Parent hooks take care of both sides
+        self.insert(child, Parent(parent));
+        Ok(())
+    }
+
+    /// Removes `child` from its parent, returning the parent it had
+    pub fn detach(&mut self, child: Entity) -> Option<Entity> {
+        self.remove::<Parent>(child).map(|Parent(parent)| parent)
+    }
+
+    /// The parent of `child`, if it has one
+    ///
+    /// `despawn` detaches the children of whatever it despawns, but a Parent can still be left pointing
+    /// at a dead Entity when it was loaded or inserted by hand. The generation check catches that even if
+    /// the slot has been reused since
+    pub fn parent(&self, child: Entity) -> Result<Option<Entity>, HierarchyError> {
+        match self.get::<Parent>(child) {
+            Some(&Parent(parent)) if !self.contains(parent) => Err(HierarchyError::DanglingParent { child, parent }),
+            Some(&Parent(parent)) => Ok(Some(parent)),
+            None => Ok(None),
+        }
+    }
+
+    /// The children of `parent`, empty if it has none
+    pub fn children(&self, parent: Entity) -> &[Entity] {
+        self.get::<Children>(parent).map_or(&[], |children| &children[..])
+    }
+
+    /// Despawns the Entity along with every descendant, dropping it from its parent's Children
+    ///
+    /// Returns false if the Entity was already gone
+    pub fn despawn_recursive(&mut self, entity: Entity) -> bool {
+        if !self.contains(entity) {
+            return false;
+        }
+        let mut stack = vec![entity];
+        while let Some(entity) = stack.pop() {
+            stack.extend_from_slice(self.children(entity));
+            self.despawn(entity);
+        }
+        true
+    }
+
+    /// Removes `child` from the Children of `parent`, dropping Children once it is empty
+    fn remove_child(&mut self, parent: Entity, child: Entity) {
+        let empty = match self.get_mut::<Children>(parent) {
+            Some(mut children) => {
+                children.0.retain(|&c| c != child);
+                children.is_empty()
+            }
+            None => false,
+        };
+        if empty {
+            self.remove::<Children>(parent);
+        }
+    }
+
+    fn is_ancestor_or_self(&self, ancestor: Entity, mut entity: Entity) -> bool {
+        // Bounded by the Entity count in case the hierarchy was corrupted by hand
+        for _ in 0..=self.len() {
+            if entity == ancestor {
+                return true;
+            }
+            match self.get::<Parent>(entity) {
+                Some(&Parent(parent)) => entity = parent,
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+impl<'a, 'w, 's> EntityCommands<'a, 'w, 's> {
+    /// Records a `World::reparent`, panics when applied if it would create a cycle. Skipped if either
+    /// Entity is gone by then
+    pub fn set_parent(&mut self, parent: Entity) -> &mut EntityCommands<'a, 'w, 's> {
+        let child = self.id();
+        self.commands().add(move |world| match world.reparent(child, parent) {
+            Ok(()) | Err(HierarchyError::NoSuchEntity(_)) => {}
+            Err(error) => panic!("{}", error),
+        });
+        self
+    }
+
+    /// Records a `World::detach`
+    pub fn remove_parent(&mut self) -> &mut EntityCommands<'a, 'w, 's> {
+        let child = self.id();
+        self.commands().add(move |world| {
+            world.detach(child);
+        });
+        self
+    }
+
+    /// Records a `World::despawn_recursive`
+    pub fn despawn_recursive(&mut self) {
+        let entity = self.id();
+        self.commands().add(move |world| {
+            world.despawn_recursive(entity);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> (World, [Entity; 3]) {
+        let mut world = World::new();
+        let entities = [world.spawn(()), world.spawn(()), world.spawn(())];
+        (world, entities)
+    }
+
+    #[test]
+    fn attach_reparent_and_detach_keep_both_sides_in_sync() {
+        let (mut world, [root, a, b]) = world();
+        world.attach(a, root).unwrap();
+        world.attach(b, root).unwrap();
+        assert_eq!(world.children(root), &[a, b]);
+        assert_eq!(world.parent(a), Ok(Some(root)));
+        assert_eq!(world.attach(a, b), Err(HierarchyError::AlreadyAttached { child: a, parent: root }));
+
+        world.reparent(a, b).unwrap();
+        assert_eq!(world.children(root), &[b]);
+        assert_eq!(world.children(b), &[a]);
+        assert_eq!(world.parent(a), Ok(Some(b)));
+
+        assert_eq!(world.detach(a), Some(b));
+        assert_eq!(world.detach(a), None);
+        assert_eq!(world.parent(a), Ok(None));
+        assert!(world.get::<Children>(b).is_none());
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let (mut world, [root, a, b]) = world();
+        world.attach(a, root).unwrap();
+        world.attach(b, a).unwrap();
+
+        assert_eq!(world.reparent(root, b), Err(HierarchyError::Cycle { child: root, parent: b }));
+        assert_eq!(world.reparent(a, a), Err(HierarchyError::Cycle { child: a, parent: a }));
+        assert_eq!(world.parent(root), Ok(None));
+        assert_eq!(world.children(a), &[b]);
+    }
+
+    #[test]
+    fn despawning_a_child_removes_it_from_its_parent() {
+        let (mut world, [root, a, b]) = world();
+        world.attach(a, root).unwrap();
+        world.attach(b, root).unwrap();
+
+        assert!(world.despawn(a));
+        assert_eq!(world.children(root), &[b]);
+        assert!(world.despawn(b));
+        assert!(world.get::<Children>(root).is_none());
+    }
+
+    #[test]
+    fn despawning_a_parent_leaves_its_children_as_roots() {
+        let (mut world, [root, a, b]) = world();
+        world.attach(a, root).unwrap();
+        world.attach(b, a).unwrap();
+
+        assert!(world.despawn(a));
+        assert_eq!(world.parent(b), Ok(None));
+        assert!(world.children(root).is_empty());
+        assert!(world.contains(b));
+    }
+
+    #[test]
+    fn despawn_recursive_takes_every_descendant() {
+        let (mut world, [root, a, b]) = world();
+        let other = world.spawn(());
+        world.attach(a, root).unwrap();
+        world.attach(b, a).unwrap();
+        world.attach(other, root).unwrap();
+
+        assert!(world.despawn_recursive(a));
+        assert!(!world.contains(a) && !world.contains(b));
+        assert_eq!(world.children(root), &[other]);
+        assert!(!world.despawn_recursive(a));
+        assert_eq!(world.reparent(a, root), Err(HierarchyError::NoSuchEntity(a)));
+    }
+
+    #[test]
+    fn removing_parent_directly_updates_the_old_parent() {
+        let (mut world, [root, a, b]) = world();
+        world.attach(a, root).unwrap();
+        world.attach(b, root).unwrap();
+
+        assert_eq!(world.remove::<Parent>(a), Some(Parent(root)));
+        assert_eq!(world.children(root), &[b]);
+        world.remove::<Parent>(b);
+        assert!(world.get::<Children>(root).is_none());
+    }
+
+    #[test]
+    fn inserting_a_copied_parent_moves_the_child() {
+        let (mut world, [root, a, b]) = world();
+        world.attach(a, root).unwrap();
+        world.attach(b, a).unwrap();
+
+        let parent = *world.get::<Parent>(a).unwrap();
+        world.insert(b, parent);
+        assert_eq!(world.children(root), &[a, b]);
+        assert!(world.get::<Children>(a).is_none());
+
+        world.insert(a, parent);
+        assert_eq!(world.children(root), &[a, b]);
+    }
+
+    #[test]
+    fn changing_children_directly_updates_the_children() {
+        let (mut world, [root, a, b]) = world();
+        world.attach(a, root).unwrap();
+        world.attach(b, root).unwrap();
+
+        world.insert(root, Children(vec![b]));
+        assert_eq!(world.parent(a), Ok(None));
+        assert_eq!(world.parent(b), Ok(Some(root)));
+
+        world.insert(a, Children(vec![b]));
+        assert_eq!(world.parent(b), Ok(Some(a)));
+        assert!(world.get::<Children>(root).is_none());
+
+        world.remove::<Children>(a);
+        assert_eq!(world.parent(b), Ok(None));
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+
+use super::command::{ CommandQueue, Commands };
+use super::component::{ Component, ComponentId };
+use super::entity_map::Entity;
+use super::world::World;
+
+/// What just happened to a Component, hooks and observers are registered for one of these
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lifecycle {
+    /// The Entity did not have the Component before, runs right after it was stored
+    Add,
+    /// The Component was added or replaced, runs right after it was stored and after Add
+    Insert,
+    /// The Component is about to be overwritten, removed or despawned, runs while the old value can
+    /// still be read and before Remove
+    Replace,
+    /// The Component is about to be removed or the Entity despawned, runs while it can still be read
+    Remove,
+}
+
+/// Passed to hooks and observers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Trigger {
+    pub event: Lifecycle,
+    pub entity: Entity,
+    pub component: ComponentId,
+}
+
+/// Hooks and observers only get to read the World, anything they change goes through Commands, which
+/// are applied as soon as the change that triggered them is done
+pub type Callback = Box<dyn Fn(&World, Trigger, &mut Commands) + Send + Sync>;
+
+/// The hooks of a single Component type, at most one for each Lifecycle event
+///
+/// Hooks are meant for the invariants of the type itself and belong to whoever owns it, anything else
+/// should use observers
+#[derive(Default)]
+pub struct ComponentHooks {
+    on_add: Option<Callback>,
+    on_insert: Option<Callback>,
+    on_replace: Option<Callback>,
+    on_remove: Option<Callback>,
+}
+
+impl ComponentHooks {
+    /// Panics if the hook is already set
+    pub fn on_add(&mut self, hook: impl Fn(&World, Trigger, &mut Commands) + Send + Sync + 'static) -> &mut ComponentHooks {
+        set_hook(&mut self.on_add, "on_add", hook);
+        self
+    }
+
+    /// Panics if the hook is already set
+    pub fn on_insert(&mut self, hook: impl Fn(&World, Trigger, &mut Commands) + Send + Sync + 'static) -> &mut ComponentHooks {
+        set_hook(&mut self.on_insert, "on_insert", hook);
+        self
+    }
+
+    /// Panics if the hook is already set
+    pub fn on_replace(&mut self, hook: impl Fn(&World, Trigger, &mut Commands) + Send + Sync + 'static) -> &mut ComponentHooks {
+        set_hook(&mut self.on_replace, "on_replace", hook);
+        self
+    }
+
+    /// Panics if the hook is already set
+    pub fn on_remove(&mut self, hook: impl Fn(&World, Trigger, &mut Commands) + Send + Sync + 'static) -> &mut ComponentHooks {
+        set_hook(&mut self.on_remove, "on_remove", hook);
+        self
+    }
+
+    fn get(&self, event: Lifecycle) -> Option<&Callback> {
+        match event {
+            Lifecycle::Add => self.on_add.as_ref(),
+            Lifecycle::Insert => self.on_insert.as_ref(),
+            Lifecycle::Replace => self.on_replace.as_ref(),
+            Lifecycle::Remove => self.on_remove.as_ref(),
+        }
+    }
+}
+
+fn set_hook(slot: &mut Option<Callback>, name: &str, hook: impl Fn(&World, Trigger, &mut Commands) + Send + Sync + 'static) {
+    if slot.is_some() {
+        panic!("component already has an {} hook", name);
+    }
+    *slot = Some(Box::new(hook));
+}
+
+/// Handed out by `World::observe`, for removing the observer again
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObserverId(usize);
+
+struct Observer {
+    id: ObserverId,
+    event: Lifecycle,
+    /// None to observe every Component
+    component: Option<ComponentId>,
+    callback: Callback,
+}
+
+/// Every hook and observer of a World
+#[derive(Default)]
+pub struct Observers {
+    hooks: HashMap<ComponentId, ComponentHooks>,
+    observers: Vec<Observer>,
+    next_id: usize,
+}
+
+impl Observers {
+    pub fn hooks(&self, component: ComponentId) -> Option<&ComponentHooks> {
+        self.hooks.get(&component)
+    }
+
+    /// Number of observers, hooks not included
+    pub fn len(&self) -> usize {
+        self.observers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+
+    fn add(&mut self, event: Lifecycle, component: Option<ComponentId>, callback: Callback) -> ObserverId {
+        let id = ObserverId(self.next_id);
+        self.next_id += 1;
+        self.observers.push(Observer {
+            id,
+            event,
+            component,
+            callback,
+        });
+        id
+    }
+}
+
+impl World {
+    /// The hooks of C, registering C with the default StorageType if it is new
+    pub fn component_hooks<C: Component>(&mut self) -> &mut ComponentHooks {
+        let id = self.init_component::<C>();
+        self.observers_mut().hooks.entry(id).or_default()
+    }
+
+    /// Runs `observer` whenever `event` happens to a C, after C's own hook for Add and Insert and
+    /// before it for Replace and Remove
+    pub fn observe<C: Component>(
+        &mut self,
+        event: Lifecycle,
+        observer: impl Fn(&World, Trigger, &mut Commands) + Send + Sync + 'static,
+    ) -> ObserverId {
+        let id = self.init_component::<C>();
+        self.observers_mut().add(event, Some(id), Box::new(observer))
+    }
+
+    /// Runs `observer` whenever `event` happens to any Component
+    pub fn observe_any(
+        &mut self,
+        event: Lifecycle,
+        observer: impl Fn(&World, Trigger, &mut Commands) + Send + Sync + 'static,
+    ) -> ObserverId {
+        self.observers_mut().add(event, None, Box::new(observer))
+    }
+
+    /// Returns false if the observer was already removed
+    pub fn remove_observer(&mut self, id: ObserverId) -> bool {
+        let observers = &mut self.observers_mut().observers;
+        match observers.iter().position(|observer| observer.id == id) {
+            Some(index) => {
+                observers.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs the hooks and observers for `event` on each of the Entity's `components`, in order,
+    /// recording their Commands into `queue`
+    ///
+    /// Called by the World itself around every change, it is up to the caller to apply the queue once
+    /// the change is done
+    pub fn trigger(&self, event: Lifecycle, entity: Entity, components: &[ComponentId], queue: &mut CommandQueue) {
+        let observers = self.observers();
+        if observers.hooks.is_empty() && observers.observers.is_empty() {
+            return;
+        }
+
+        // Hooks stay closest to the change, before observers once it is done and after them while it is
+        // still coming
+        let hook_first = matches!(event, Lifecycle::Add | Lifecycle::Insert);
+        let mut commands = Commands::new(queue, self);
+        for &component in components {
+            let trigger = Trigger {
+                event,
+                entity,
+                component,
+            };
+            let hook = observers.hooks.get(&component).and_then(|hooks| hooks.get(event));
+            if hook_first {
+                if let Some(hook) = hook {
+                    hook(self, trigger, &mut commands);
+                }
+            }
+            for observer in &observers.observers {
+                if observer.event == event && observer.component.is_none_or(|observed| observed == component) {
+                    (observer.callback)(self, trigger, &mut commands);
+                }
+            }
+            if !hook_first {
+                if let Some(hook) = hook {
+                    hook(self, trigger, &mut commands);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{ Arc, Mutex };
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct Alive;
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    fn logger(log: &Log, name: &'static str) -> impl Fn(&World, Trigger, &mut Commands) + Send + Sync + 'static {
+        let log = log.clone();
+        move |world, trigger, _| {
+            let health = world.get::<Health>(trigger.entity).map(|health| health.0);
+            log.lock().unwrap().push(format!("{} {:?}", name, health));
+        }
+    }
+
+    fn take(log: &Log) -> Vec<String> {
+        std::mem::take(&mut *log.lock().unwrap())
+    }
+
+    fn world(log: &Log) -> World {
+        let mut world = World::new();
+        world
+            .component_hooks::<Health>()
+            .on_add(logger(log, "hook add"))
+            .on_insert(logger(log, "hook insert"))
+            .on_replace(logger(log, "hook replace"))
+            .on_remove(logger(log, "hook remove"));
+        world.observe::<Health>(Lifecycle::Add, logger(log, "observer add"));
+        world.observe::<Health>(Lifecycle::Insert, logger(log, "observer insert"));
+        world.observe::<Health>(Lifecycle::Replace, logger(log, "observer replace"));
+        world.observe::<Health>(Lifecycle::Remove, logger(log, "observer remove"));
+        world
+    }
+
+    #[test]
+    fn hooks_run_closest_to_the_change() {
+        let log = Log::default();
+        let mut world = world(&log);
+
+        let entity = world.spawn((Health(1),));
+        assert_eq!(
+            take(&log),
+            vec!["hook add Some(1)", "observer add Some(1)", "hook insert Some(1)", "observer insert Some(1)"]
+        );
+
+        world.insert(entity, Health(2));
+        assert_eq!(
+            take(&log),
+            vec!["observer replace Some(1)", "hook replace Some(1)", "hook insert Some(2)", "observer insert Some(2)"]
+        );
+
+        world.remove::<Health>(entity);
+        assert_eq!(
+            take(&log),
+            vec!["observer replace Some(2)", "hook replace Some(2)", "observer remove Some(2)", "hook remove Some(2)"]
+        );
+        assert!(world.get::<Health>(entity).is_none());
+
+        world.insert(entity, Health(3));
+        take(&log);
+        world.despawn(entity);
+        assert_eq!(
+            take(&log),
+            vec!["observer replace Some(3)", "hook replace Some(3)", "observer remove Some(3)", "hook remove Some(3)"]
+        );
+
+        let entity = world.spawn((Health(4),));
+        take(&log);
+        world.insert_bundle(entity, (Health(5), Alive));
+        assert_eq!(
+            take(&log),
+            vec!["observer replace Some(4)", "hook replace Some(4)", "hook insert Some(5)", "observer insert Some(5)"]
+        );
+    }
+
+    #[test]
+    fn commands_from_hooks_are_applied_once_the_change_is_done() {
+        let mut world = World::new();
+        world.component_hooks::<Health>().on_add(|world, trigger, commands| {
+            assert!(world.get::<Alive>(trigger.entity).is_none());
+            commands.entity(trigger.entity).insert(Alive);
+        });
+        world.component_hooks::<Alive>().on_remove(|_, trigger, commands| {
+            commands.entity(trigger.entity).remove::<Health>();
+        });
+
+        let entity = world.spawn((Health(1),));
+        assert_eq!(world.get::<Alive>(entity), Some(&Alive));
+        world.remove::<Alive>(entity);
+        assert!(world.get::<Health>(entity).is_none());
+    }
+
+    #[test]
+    fn observers_can_watch_everything_and_be_removed() {
+        let log = Log::default();
+        let mut world = World::new();
+        let any = world.observe_any(Lifecycle::Add, logger(&log, "any"));
+        let health = world.observe::<Health>(Lifecycle::Add, logger(&log, "health"));
+        assert_eq!(world.observers().len(), 2);
+
+        world.spawn((Health(1), Alive));
+        assert_eq!(take(&log), vec!["any Some(1)", "health Some(1)", "any Some(1)"]);
+
+        assert!(world.remove_observer(any));
+        assert!(!world.remove_observer(any));
+        world.spawn((Alive,));
+        assert!(take(&log).is_empty());
+
+        assert!(world.remove_observer(health));
+        assert!(world.observers().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "component already has an on_add hook")]
+    fn hooks_are_set_once() {
+        let mut world = World::new();
+        world.component_hooks::<Health>().on_add(|_, _, _| {});
+        world.component_hooks::<Health>().on_add(|_, _, _| {});
+    }
+}
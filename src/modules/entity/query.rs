@@ -0,0 +1,809 @@
+use std::marker::PhantomData;
+
+use super::access::Access;
+use super::archetype::{ Archetype, ArchetypeId };
+use super::change_detection::{ ComponentTicks, Mut, Ref, Tick };
+use super::component::{ Component, ComponentId, Components };
+use super::entity_map::Entity;
+use super::storage::{ BoxedStorage, ComponentCell, SyncCell };
+use super::world::{ World, WorldId };
+
+/// Something that can be fetched for every Entity in a matching Archetype, e.g. `&T`, `&mut T`,
+/// `Option<&T>` or a tuple of those
+///
+/// # Safety
+/// `update_access` has to report every Component `fetch` reads or writes, the Query relies on it to
+/// rule out aliasing `&mut`
+pub unsafe trait WorldQuery {
+    type Item<'w>;
+    type Fetch<'w>;
+    type State: Send + Sync + 'static;
+
+    fn init_state(world: &mut World) -> Self::State;
+    fn update_access(state: &Self::State, access: &mut Access, components: &Components);
+    fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool;
+
+    /// Change ticks are compared against `last_run` and writes are stamped with `this_run`
+    ///
+    /// # Safety
+    /// The archetype has to match and belong to `world`, and the caller has to hold the access
+    /// reported by `update_access` for as long as the Fetch lives
+    unsafe fn init_fetch<'w>(
+        world: &'w World,
+        state: &Self::State,
+        archetype: &'w Archetype,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> Self::Fetch<'w>;
+
+    /// # Safety
+    /// `row` has to be a row of the archetype the Fetch was made for and hold `entity`, and the same
+    /// row must not be fetched twice while a mutable item from it is alive
+    unsafe fn fetch<'w>(fetch: &mut Self::Fetch<'w>, entity: Entity, row: usize) -> Self::Item<'w>;
+}
+
+/// Marks WorldQueries that never hand out mutable references
+///
+/// # Safety
+/// `update_access` must only ever add reads
+pub unsafe trait ReadOnlyWorldQuery: WorldQuery {}
+
+/// Narrows down which Entities a Query visits without fetching anything, e.g. `With<T>`, `Without<T>`
+/// or a tuple of those
+///
+/// # Safety
+/// Same contract as WorldQuery
+pub unsafe trait QueryFilter {
+    type Fetch<'w>;
+    type State: Send + Sync + 'static;
+
+    fn init_state(world: &mut World) -> Self::State;
+    fn update_access(state: &Self::State, access: &mut Access, components: &Components);
+    fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool;
+
+    /// # Safety
+    /// See WorldQuery::init_fetch
+    unsafe fn init_fetch<'w>(
+        world: &'w World,
+        state: &Self::State,
+        archetype: &'w Archetype,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> Self::Fetch<'w>;
+
+    /// # Safety
+    /// See WorldQuery::fetch
+    unsafe fn filter_fetch(fetch: &mut Self::Fetch<'_>, entity: Entity, row: usize) -> bool;
+}
+
+type TableCells<'w, T> = (&'w [SyncCell<T>], &'w [SyncCell<ComponentTicks>]);
+
+/// Finds the cells holding a Component and its change ticks for an Entity, whichever StorageType the
+/// Component uses
+pub struct ComponentFetch<'w, T> {
+    cells: Option<TableCells<'w, T>>,
+    storage: Option<&'w BoxedStorage<ComponentCell<T>>>,
+    last_run: Tick,
+    this_run: Tick,
+}
+
+impl<'w, T: Component> ComponentFetch<'w, T> {
+    pub fn new(
+        world: &'w World,
+        component: ComponentId,
+        archetype: &'w Archetype,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> ComponentFetch<'w, T> {
+        match archetype.column::<T>(component) {
+            Some(column) => ComponentFetch {
+                cells: Some((column.cells(), column.ticks())),
+                storage: None,
+                last_run,
+                this_run,
+            },
+            None => ComponentFetch {
+                cells: None,
+                storage: world.storage::<T>(component),
+                last_run,
+                this_run,
+            },
+        }
+    }
+
+    pub fn cell(&self, entity: Entity, row: usize) -> &'w SyncCell<T> {
+        self.cells(entity, row).0
+    }
+
+    pub fn ticks(&self, entity: Entity, row: usize) -> &'w SyncCell<ComponentTicks> {
+        self.cells(entity, row).1
+    }
+
+    pub fn cells(&self, entity: Entity, row: usize) -> (&'w SyncCell<T>, &'w SyncCell<ComponentTicks>) {
+        match (self.cells, self.storage) {
+            (Some((cells, ticks)), _) => (&cells[row], &ticks[row]),
+            (None, Some(storage)) => {
+                let cell = storage.get(entity).expect("archetype and component storage disagree");
+                (cell.value(), cell.ticks())
+            }
+            (None, None) => panic!("component storage missing for {}", entity),
+        }
+    }
+
+    pub fn last_run(&self) -> Tick {
+        self.last_run
+    }
+
+    pub fn this_run(&self) -> Tick {
+        self.this_run
+    }
+}
+
+unsafe impl<T: Component> WorldQuery for &T {
+    type Item<'w> = &'w T;
+    type Fetch<'w> = ComponentFetch<'w, T>;
+    type State = ComponentId;
+
+    fn init_state(world: &mut World) -> ComponentId {
+        world.init_component::<T>()
+    }
+
+    fn update_access(state: &ComponentId, access: &mut Access, components: &Components) {
+        access.add_read(*state, components);
+    }
+
+    fn matches_archetype(state: &ComponentId, archetype: &Archetype) -> bool {
+        archetype.contains(*state)
+    }
+
+    unsafe fn init_fetch<'w>(
+        world: &'w World,
+        state: &ComponentId,
+        archetype: &'w Archetype,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> Self::Fetch<'w> {
+        ComponentFetch::new(world, *state, archetype, last_run, this_run)
+    }
+
+    unsafe fn fetch<'w>(fetch: &mut Self::Fetch<'w>, entity: Entity, row: usize) -> Self::Item<'w> {
+        fetch.cell(entity, row).deref()
+    }
+}
+
+unsafe impl<T: Component> ReadOnlyWorldQuery for &T {}
+
+/// Fetches a Mut, which marks the value changed once it is written through
+unsafe impl<T: Component> WorldQuery for &mut T {
+    type Item<'w> = Mut<'w, T>;
+    type Fetch<'w> = ComponentFetch<'w, T>;
+    type State = ComponentId;
+
+    fn init_state(world: &mut World) -> ComponentId {
+        world.init_component::<T>()
+    }
+
+    fn update_access(state: &ComponentId, access: &mut Access, components: &Components) {
+        access.add_write(*state, components);
+    }
+
+    fn matches_archetype(state: &ComponentId, archetype: &Archetype) -> bool {
+        archetype.contains(*state)
+    }
+
+    unsafe fn init_fetch<'w>(
+        world: &'w World,
+        state: &ComponentId,
+        archetype: &'w Archetype,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> Self::Fetch<'w> {
+        ComponentFetch::new(world, *state, archetype, last_run, this_run)
+    }
+
+    unsafe fn fetch<'w>(fetch: &mut Self::Fetch<'w>, entity: Entity, row: usize) -> Self::Item<'w> {
+        let (cell, ticks) = fetch.cells(entity, row);
+        Mut::new(&mut *cell.get(), &mut *ticks.get(), fetch.last_run, fetch.this_run)
+    }
+}
+
+/// Like `&T` but the item also tells whether the value was added or changed since the last run
+unsafe impl<'a, T: Component> WorldQuery for Ref<'a, T> {
+    type Item<'w> = Ref<'w, T>;
+    type Fetch<'w> = ComponentFetch<'w, T>;
+    type State = ComponentId;
+
+    fn init_state(world: &mut World) -> ComponentId {
+        world.init_component::<T>()
+    }
+
+    fn update_access(state: &ComponentId, access: &mut Access, components: &Components) {
+        access.add_read(*state, components);
+    }
+
+    fn matches_archetype(state: &ComponentId, archetype: &Archetype) -> bool {
+        archetype.contains(*state)
+    }
+
+    unsafe fn init_fetch<'w>(
+        world: &'w World,
+        state: &ComponentId,
+        archetype: &'w Archetype,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> Self::Fetch<'w> {
+        ComponentFetch::new(world, *state, archetype, last_run, this_run)
+    }
+
+    unsafe fn fetch<'w>(fetch: &mut Self::Fetch<'w>, entity: Entity, row: usize) -> Self::Item<'w> {
+        let (cell, ticks) = fetch.cells(entity, row);
+        Ref::new(cell.deref(), ticks.deref(), fetch.last_run, fetch.this_run)
+    }
+}
+
+unsafe impl<'a, T: Component> ReadOnlyWorldQuery for Ref<'a, T> {}
+
+unsafe impl<Q: WorldQuery> WorldQuery for Option<Q> {
+    type Item<'w> = Option<Q::Item<'w>>;
+    type Fetch<'w> = Option<Q::Fetch<'w>>;
+    type State = Q::State;
+
+    fn init_state(world: &mut World) -> Q::State {
+        Q::init_state(world)
+    }
+
+    fn update_access(state: &Q::State, access: &mut Access, components: &Components) {
+        Q::update_access(state, access, components);
+    }
+
+    fn matches_archetype(_state: &Q::State, _archetype: &Archetype) -> bool {
+        true
+    }
+
+    unsafe fn init_fetch<'w>(
+        world: &'w World,
+        state: &Q::State,
+        archetype: &'w Archetype,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> Self::Fetch<'w> {
+        if Q::matches_archetype(state, archetype) {
+            Some(Q::init_fetch(world, state, archetype, last_run, this_run))
+        } else {
+            None
+        }
+    }
+
+    unsafe fn fetch<'w>(fetch: &mut Self::Fetch<'w>, entity: Entity, row: usize) -> Self::Item<'w> {
+        fetch.as_mut().map(|fetch| Q::fetch(fetch, entity, row))
+    }
+}
+
+unsafe impl<Q: ReadOnlyWorldQuery> ReadOnlyWorldQuery for Option<Q> {}
+
+/// Only visits Entities that have T, without fetching it
+pub struct With<T>(PhantomData<T>);
+
+/// Only visits Entities that do not have T
+pub struct Without<T>(PhantomData<T>);
+
+unsafe impl<T: Component> QueryFilter for With<T> {
+    type Fetch<'w> = ();
+    type State = ComponentId;
+
+    fn init_state(world: &mut World) -> ComponentId {
+        world.init_component::<T>()
+    }
+
+    fn update_access(_state: &ComponentId, _access: &mut Access, _components: &Components) {}
+
+    fn matches_archetype(state: &ComponentId, archetype: &Archetype) -> bool {
+        archetype.contains(*state)
+    }
+
+    unsafe fn init_fetch<'w>(
+        _world: &'w World,
+        _state: &ComponentId,
+        _archetype: &'w Archetype,
+        _last_run: Tick,
+        _this_run: Tick,
+    ) {
+    }
+
+    unsafe fn filter_fetch(_fetch: &mut (), _entity: Entity, _row: usize) -> bool {
+        true
+    }
+}
+
+unsafe impl<T: Component> QueryFilter for Without<T> {
+    type Fetch<'w> = ();
+    type State = ComponentId;
+
+    fn init_state(world: &mut World) -> ComponentId {
+        world.init_component::<T>()
+    }
+
+    fn update_access(_state: &ComponentId, _access: &mut Access, _components: &Components) {}
+
+    fn matches_archetype(state: &ComponentId, archetype: &Archetype) -> bool {
+        !archetype.contains(*state)
+    }
+
+    unsafe fn init_fetch<'w>(
+        _world: &'w World,
+        _state: &ComponentId,
+        _archetype: &'w Archetype,
+        _last_run: Tick,
+        _this_run: Tick,
+    ) {
+    }
+
+    unsafe fn filter_fetch(_fetch: &mut (), _entity: Entity, _row: usize) -> bool {
+        true
+    }
+}
+
+/// Only visits Entities whose T was added since the System last ran
+pub struct Added<T>(PhantomData<T>);
+
+/// Only visits Entities whose T was added or mutably accessed since the System last ran
+pub struct Changed<T>(PhantomData<T>);
+
+/// Filters read T's change ticks, that is allowed next to a `&mut T` in the same Query since a row's
+/// ticks are only read before its item is fetched
+fn add_filter_read(component: ComponentId, access: &mut Access, components: &Components) {
+    if !access.has_write(component) {
+        access.add_read(component, components);
+    }
+}
+
+unsafe impl<T: Component> QueryFilter for Added<T> {
+    type Fetch<'w> = ComponentFetch<'w, T>;
+    type State = ComponentId;
+
+    fn init_state(world: &mut World) -> ComponentId {
+        world.init_component::<T>()
+    }
+
+    fn update_access(state: &ComponentId, access: &mut Access, components: &Components) {
+        add_filter_read(*state, access, components);
+    }
+
+    fn matches_archetype(state: &ComponentId, archetype: &Archetype) -> bool {
+        archetype.contains(*state)
+    }
+
+    unsafe fn init_fetch<'w>(
+        world: &'w World,
+        state: &ComponentId,
+        archetype: &'w Archetype,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> Self::Fetch<'w> {
+        ComponentFetch::new(world, *state, archetype, last_run, this_run)
+    }
+
+    unsafe fn filter_fetch(fetch: &mut Self::Fetch<'_>, entity: Entity, row: usize) -> bool {
+        fetch.ticks(entity, row).deref().is_added(fetch.last_run, fetch.this_run)
+    }
+}
+
+unsafe impl<T: Component> QueryFilter for Changed<T> {
+    type Fetch<'w> = ComponentFetch<'w, T>;
+    type State = ComponentId;
+
+    fn init_state(world: &mut World) -> ComponentId {
+        world.init_component::<T>()
+    }
+
+    fn update_access(state: &ComponentId, access: &mut Access, components: &Components) {
+        add_filter_read(*state, access, components);
+    }
+
+    fn matches_archetype(state: &ComponentId, archetype: &Archetype) -> bool {
+        archetype.contains(*state)
+    }
+
+    unsafe fn init_fetch<'w>(
+        world: &'w World,
+        state: &ComponentId,
+        archetype: &'w Archetype,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> Self::Fetch<'w> {
+        ComponentFetch::new(world, *state, archetype, last_run, this_run)
+    }
+
+    unsafe fn filter_fetch(fetch: &mut Self::Fetch<'_>, entity: Entity, row: usize) -> bool {
+        fetch.ticks(entity, row).deref().is_changed(fetch.last_run, fetch.this_run)
+    }
+}
+
+macro_rules! impl_tuple_query {
+    ($($name: ident),*) => {
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        unsafe impl<$($name: WorldQuery),*> WorldQuery for ($($name,)*) {
+            type Item<'w> = ($($name::Item<'w>,)*);
+            type Fetch<'w> = ($($name::Fetch<'w>,)*);
+            type State = ($($name::State,)*);
+
+            fn init_state(world: &mut World) -> Self::State {
+                ($($name::init_state(world),)*)
+            }
+
+            fn update_access(state: &Self::State, access: &mut Access, components: &Components) {
+                let ($($name,)*) = state;
+                $($name::update_access($name, access, components);)*
+            }
+
+            fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+                let ($($name,)*) = state;
+                true $(&& $name::matches_archetype($name, archetype))*
+            }
+
+            unsafe fn init_fetch<'w>(
+                world: &'w World,
+                state: &Self::State,
+                archetype: &'w Archetype,
+                last_run: Tick,
+                this_run: Tick,
+            ) -> Self::Fetch<'w> {
+                let ($($name,)*) = state;
+                ($($name::init_fetch(world, $name, archetype, last_run, this_run),)*)
+            }
+
+            unsafe fn fetch<'w>(fetch: &mut Self::Fetch<'w>, entity: Entity, row: usize) -> Self::Item<'w> {
+                let ($($name,)*) = fetch;
+                ($($name::fetch($name, entity, row),)*)
+            }
+        }
+
+        unsafe impl<$($name: ReadOnlyWorldQuery),*> ReadOnlyWorldQuery for ($($name,)*) {}
+
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        unsafe impl<$($name: QueryFilter),*> QueryFilter for ($($name,)*) {
+            type Fetch<'w> = ($($name::Fetch<'w>,)*);
+            type State = ($($name::State,)*);
+
+            fn init_state(world: &mut World) -> Self::State {
+                ($($name::init_state(world),)*)
+            }
+
+            fn update_access(state: &Self::State, access: &mut Access, components: &Components) {
+                let ($($name,)*) = state;
+                $($name::update_access($name, access, components);)*
+            }
+
+            fn matches_archetype(state: &Self::State, archetype: &Archetype) -> bool {
+                let ($($name,)*) = state;
+                true $(&& $name::matches_archetype($name, archetype))*
+            }
+
+            unsafe fn init_fetch<'w>(
+                world: &'w World,
+                state: &Self::State,
+                archetype: &'w Archetype,
+                last_run: Tick,
+                this_run: Tick,
+            ) -> Self::Fetch<'w> {
+                let ($($name,)*) = state;
+                ($($name::init_fetch(world, $name, archetype, last_run, this_run),)*)
+            }
+
+            unsafe fn filter_fetch(fetch: &mut Self::Fetch<'_>, entity: Entity, row: usize) -> bool {
+                let ($($name,)*) = fetch;
+                true $(&& $name::filter_fetch($name, entity, row))*
+            }
+        }
+    };
+}
+
+impl_tuple_query!();
+impl_tuple_query!(A);
+impl_tuple_query!(A, B);
+impl_tuple_query!(A, B, C);
+impl_tuple_query!(A, B, C, D);
+impl_tuple_query!(A, B, C, D, E);
+impl_tuple_query!(A, B, C, D, E, F);
+impl_tuple_query!(A, B, C, D, E, F, G);
+impl_tuple_query!(A, B, C, D, E, F, G, H);
+
+/// Everything a Query needs to run against a World, kept between runs so matching archetypes only
+/// has to look at the ones created since last time
+///
+/// Aliasing access, like `(&T, &mut T)`, panics when the state is created
+pub struct QueryState<Q: WorldQuery, F: QueryFilter = ()> {
+    world_id: WorldId,
+    query_state: Q::State,
+    filter_state: F::State,
+    access: Access,
+    matched_archetypes: Vec<ArchetypeId>,
+    archetypes_seen: usize,
+}
+
+impl<Q: WorldQuery, F: QueryFilter> QueryState<Q, F> {
+    pub fn new(world: &mut World) -> QueryState<Q, F> {
+        let query_state = Q::init_state(world);
+        let filter_state = F::init_state(world);
+        let mut access = Access::new();
+        Q::update_access(&query_state, &mut access, world.components());
+        F::update_access(&filter_state, &mut access, world.components());
+
+        let mut state = QueryState {
+            world_id: world.id(),
+            query_state,
+            filter_state,
+            access,
+            matched_archetypes: Vec::new(),
+            archetypes_seen: 0,
+        };
+        state.update_archetypes(world);
+        state
+    }
+
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    /// Picks up archetypes that were created since the last call
+    pub fn update_archetypes(&mut self, world: &World) {
+        assert_eq!(self.world_id, world.id(), "query state used with a world it was not created for");
+        for archetype in world.archetypes().iter().skip(self.archetypes_seen) {
+            if Q::matches_archetype(&self.query_state, archetype)
+                && F::matches_archetype(&self.filter_state, archetype)
+            {
+                self.matched_archetypes.push(archetype.id());
+            }
+        }
+        self.archetypes_seen = world.archetypes().len();
+    }
+
+    pub fn iter<'w, 's>(&'s mut self, world: &'w World) -> QueryIter<'w, 's, Q, F>
+    where
+        Q: ReadOnlyWorldQuery,
+    {
+        self.update_archetypes(world);
+        // Only reads can be handed out and `&World` rules out anyone writing
+        unsafe { self.iter_unchecked(world, world.last_change_tick(), world.change_tick()) }
+    }
+
+    pub fn iter_mut<'w, 's>(&'s mut self, world: &'w mut World) -> QueryIter<'w, 's, Q, F> {
+        self.update_archetypes(world);
+        let (last_run, this_run) = (world.last_change_tick(), world.change_tick());
+        // `&mut World` means nothing else can be looking at the World
+        unsafe { self.iter_unchecked(world, last_run, this_run) }
+    }
+
+    /// Iterates without updating the matched archetypes, change detection treats `last_run` as the
+    /// last time the caller looked and `this_run` as now
+    ///
+    /// # Safety
+    /// The caller has to make sure nothing else accesses the Components in `access` in a conflicting
+    /// way while the iterator or its items are alive
+    pub unsafe fn iter_unchecked<'w, 's>(
+        &'s self,
+        world: &'w World,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> QueryIter<'w, 's, Q, F> {
+        assert_eq!(self.world_id, world.id(), "query state used with a world it was not created for");
+        QueryIter {
+            world,
+            state: self,
+            last_run,
+            this_run,
+            next_archetype: 0,
+            current: None,
+            row: 0,
+        }
+    }
+
+    pub fn get<'w>(&mut self, world: &'w World, entity: Entity) -> Option<Q::Item<'w>>
+    where
+        Q: ReadOnlyWorldQuery,
+    {
+        self.update_archetypes(world);
+        unsafe { self.get_unchecked(world, entity, world.last_change_tick(), world.change_tick()) }
+    }
+
+    pub fn get_mut<'w>(&mut self, world: &'w mut World, entity: Entity) -> Option<Q::Item<'w>> {
+        self.update_archetypes(world);
+        let (last_run, this_run) = (world.last_change_tick(), world.change_tick());
+        unsafe { self.get_unchecked(world, entity, last_run, this_run) }
+    }
+
+    /// Fetches a single Entity, None if it is gone or does not match
+    ///
+    /// # Safety
+    /// Same as `iter_unchecked`
+    pub unsafe fn get_unchecked<'w>(
+        &self,
+        world: &'w World,
+        entity: Entity,
+        last_run: Tick,
+        this_run: Tick,
+    ) -> Option<Q::Item<'w>> {
+        assert_eq!(self.world_id, world.id(), "query state used with a world it was not created for");
+        let location = world.location(entity)?;
+        self.matched_archetypes.binary_search(&location.archetype).ok()?;
+
+        let archetype = world.archetypes().get(location.archetype);
+        let mut filter = F::init_fetch(world, &self.filter_state, archetype, last_run, this_run);
+        if !F::filter_fetch(&mut filter, entity, location.row) {
+            return None;
+        }
+        let mut fetch = Q::init_fetch(world, &self.query_state, archetype, last_run, this_run);
+        Some(Q::fetch(&mut fetch, entity, location.row))
+    }
+
+    /// True if the Entity exists, matches Q and passes F. Only the filter is fetched, Q's item is
+    /// never made
+    ///
+    /// # Safety
+    /// Same as `iter_unchecked`, filters read change ticks
+    pub unsafe fn contains_unchecked(&self, world: &World, entity: Entity, last_run: Tick, this_run: Tick) -> bool {
+        assert_eq!(self.world_id, world.id(), "query state used with a world it was not created for");
+        let location = match world.location(entity) {
+            Some(location) => location,
+            None => return false,
+        };
+        if self.matched_archetypes.binary_search(&location.archetype).is_err() {
+            return false;
+        }
+
+        let archetype = world.archetypes().get(location.archetype);
+        let mut filter = F::init_fetch(world, &self.filter_state, archetype, last_run, this_run);
+        F::filter_fetch(&mut filter, entity, location.row)
+    }
+}
+
+/// Walks every matching Entity, yielding it alongside the fetched data
+pub struct QueryIter<'w, 's, Q: WorldQuery, F: QueryFilter> {
+    world: &'w World,
+    state: &'s QueryState<Q, F>,
+    last_run: Tick,
+    this_run: Tick,
+    next_archetype: usize,
+    current: Option<(&'w Archetype, Q::Fetch<'w>, F::Fetch<'w>)>,
+    row: usize,
+}
+
+impl<'w, 's, Q: WorldQuery, F: QueryFilter> Iterator for QueryIter<'w, 's, Q, F> {
+    type Item = (Entity, Q::Item<'w>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((archetype, fetch, filter)) = &mut self.current {
+                while self.row < archetype.len() {
+                    let row = self.row;
+                    self.row += 1;
+                    let entity = archetype.entities()[row];
+
+                    // Every row is visited once and the access was checked when the iterator was made
+                    unsafe {
+                        if F::filter_fetch(filter, entity, row) {
+                            return Some((entity, Q::fetch(fetch, entity, row)));
+                        }
+                    }
+                }
+            }
+
+            let id = *self.state.matched_archetypes.get(self.next_archetype)?;
+            self.next_archetype += 1;
+            let archetype = self.world.archetypes().get(id);
+            // The archetype was matched against both Q and F
+            self.current = unsafe {
+                Some((
+                    archetype,
+                    Q::init_fetch(self.world, &self.state.query_state, archetype, self.last_run, self.this_run),
+                    F::init_fetch(self.world, &self.state.filter_state, archetype, self.last_run, self.this_run),
+                ))
+            };
+            self.row = 0;
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let current = match &self.current {
+            Some((archetype, _, _)) => archetype.len() - self.row,
+            None => 0,
+        };
+        let remaining: usize = self.state.matched_archetypes[self.next_archetype..]
+            .iter()
+            .map(|id| self.world.archetypes().get(*id).len())
+            .sum();
+        (0, Some(current + remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+
+    #[derive(Debug, PartialEq)]
+    struct Velocity(i32);
+
+    struct Frozen;
+
+    /// Three Entities with a Position, the first two moving and the second one frozen
+    fn world() -> (World, Vec<Entity>) {
+        let mut world = World::new();
+        let entities = vec![
+            world.spawn((Position(0), Velocity(1))),
+            world.spawn((Position(10), Velocity(2), Frozen)),
+            world.spawn((Position(20),)),
+        ];
+        (world, entities)
+    }
+
+    #[test]
+    fn tuple_queries_only_visit_matching_entities() {
+        let (mut world, entities) = world();
+        let mut query = world.query::<(&Position, &Velocity)>();
+        let mut found: Vec<_> = query.iter(&world).map(|(entity, (p, v))| (entity, p.0, v.0)).collect();
+        found.sort();
+        assert_eq!(found, vec![(entities[0], 0, 1), (entities[1], 10, 2)]);
+    }
+
+    #[test]
+    fn mutable_queries_write_through() {
+        let (mut world, entities) = world();
+        let mut query = world.query::<(&mut Position, &Velocity)>();
+        for (_, (mut position, velocity)) in query.iter_mut(&mut world) {
+            position.0 += velocity.0;
+        }
+        assert_eq!(world.get::<Position>(entities[0]), Some(&Position(1)));
+        assert_eq!(world.get::<Position>(entities[1]), Some(&Position(12)));
+        assert_eq!(world.get::<Position>(entities[2]), Some(&Position(20)));
+    }
+
+    #[test]
+    fn with_without_and_option_filter_as_expected() {
+        let (mut world, entities) = world();
+        let mut moving = world.query_filtered::<&Position, (With<Velocity>, Without<Frozen>)>();
+        assert_eq!(moving.iter(&world).map(|(entity, _)| entity).collect::<Vec<_>>(), vec![entities[0]]);
+
+        let mut optional = world.query::<(&Position, Option<&Velocity>)>();
+        let mut found: Vec<_> = optional.iter(&world).map(|(_, (p, v))| (p.0, v.map(|v| v.0))).collect();
+        found.sort();
+        assert_eq!(found, vec![(0, Some(1)), (10, Some(2)), (20, None)]);
+    }
+
+    #[test]
+    fn query_state_picks_up_new_archetypes() {
+        let (mut world, _) = world();
+        let mut query = world.query::<&Velocity>();
+        assert_eq!(query.iter(&world).count(), 2);
+        let late = world.spawn((Velocity(3), Frozen, Position(1)));
+        world.insert(late, 5u8);
+        assert_eq!(query.iter(&world).count(), 3);
+        assert_eq!(query.get(&world, late).map(|v| v.0), Some(3));
+    }
+
+    #[test]
+    fn get_misses_despawned_and_filtered_entities() {
+        let (mut world, entities) = world();
+        let mut query = world.query_filtered::<&Position, Without<Frozen>>();
+        assert_eq!(query.get(&world, entities[0]), Some(&Position(0)));
+        assert_eq!(query.get(&world, entities[1]), None);
+        world.despawn(entities[0]);
+        assert_eq!(query.get(&world, entities[0]), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "accessed both immutably and mutably")]
+    fn aliasing_queries_panic() {
+        let mut world = World::new();
+        world.query::<(&mut Position, &Position)>();
+    }
+
+    #[test]
+    #[should_panic(expected = "accessed mutably more than once")]
+    fn double_mutable_queries_panic() {
+        let mut world = World::new();
+        world.query::<(&mut Position, Option<&mut Position>)>();
+    }
+}
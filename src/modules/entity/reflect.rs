@@ -0,0 +1,523 @@
+use std::any::{ type_name, Any, TypeId };
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::mem::size_of;
+
+use super::component::Component;
+use super::entity_map::Entity;
+use super::resource::Resource;
+use super::value::{ Persist, Value, ValueError };
+use super::world::World;
+
+/// Name and type of one field of a reflected type
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    pub type_name: &'static str,
+}
+
+/// A type whose layout can be inspected at runtime, its values are read and written as Value trees
+///
+/// Usually implemented with `reflect_struct!`, which takes care of Persist as well
+pub trait Reflect: Persist {
+    /// The named fields of the type, in declaration order. Empty for anything that is not a struct
+    /// with named fields
+    fn fields() -> &'static [FieldInfo];
+
+    /// Read-only types can be read through reflection but not set or removed, for Components that have
+    /// to stay in sync with others, like Parent and Children. Snapshots still save and load them
+    fn read_only() -> bool {
+        false
+    }
+}
+
+/// Implements Persist and Reflect for a struct with named fields, every field has to implement Persist
+///
+/// ```ignore
+/// reflect_struct!(Velocity { linear: Vec3, angular: Vec3 });
+/// ```
+#[macro_export]
+macro_rules! reflect_struct {
+    ($t:ident { $($field:ident: $field_type:ty),* $(,)? }) => {
+        impl $crate::modules::entity::value::Persist for $t {
+            fn to_value(&self) -> $crate::modules::entity::value::Value {
+                $crate::modules::entity::value::Value::Struct(vec![$(
+                    (
+                        stringify!($field).to_string(),
+                        $crate::modules::entity::value::Persist::to_value(&self.$field),
+                    ),
+                )*])
+            }
+
+            fn from_value(
+                value: &$crate::modules::entity::value::Value,
+            ) -> Result<$t, $crate::modules::entity::value::ValueError> {
+                Ok($t {
+                    $($field: value.read_field::<$field_type>(stringify!($field))?,)*
+                })
+            }
+        }
+
+        impl $crate::modules::entity::reflect::Reflect for $t {
+            fn fields() -> &'static [$crate::modules::entity::reflect::FieldInfo] {
+                &[$($crate::modules::entity::reflect::FieldInfo {
+                    name: stringify!($field),
+                    type_name: stringify!($field_type),
+                },)*]
+            }
+        }
+    };
+}
+
+/// A Value converted to the registered type, boxed so it can be handed around without knowing it
+pub type Converted = Box<dyn Any>;
+
+fn convert<T: Reflect + 'static>(value: &Value) -> Result<Converted, ValueError> {
+    Ok(Box::new(T::from_value(value)?))
+}
+
+fn downcast<T: 'static>(value: Converted) -> T {
+    *value.downcast::<T>().expect("value converted by a different registration")
+}
+
+/// Everything known about a registered Component type, enough to work with it without knowing it
+#[derive(Clone)]
+pub struct TypeRegistration {
+    name: String,
+    type_name: &'static str,
+    type_id: TypeId,
+    size: usize,
+    fields: &'static [FieldInfo],
+    read_only: bool,
+    get: fn(&World, Entity) -> Option<Value>,
+    convert: fn(&Value) -> Result<Converted, ValueError>,
+    insert: fn(&mut World, Entity, Converted),
+    remove: fn(&mut World, Entity) -> bool,
+}
+
+impl TypeRegistration {
+    /// The name it was registered under
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The full Rust path of the type
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn type_id(&self) -> TypeId {
+        self.type_id
+    }
+
+    /// Size of a value in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn fields(&self) -> &'static [FieldInfo] {
+        self.fields
+    }
+
+    pub fn field(&self, name: &str) -> Option<&'static FieldInfo> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// See `Reflect::read_only`
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// A copy of the Entity's Component, None if it does not have one
+    pub fn get(&self, world: &World, entity: Entity) -> Option<Value> {
+        (self.get)(world, entity)
+    }
+
+    pub fn convert(&self, value: &Value) -> Result<Converted, ValueError> {
+        (self.convert)(value)
+    }
+
+    /// Inserts a value returned by `convert` of this registration, read-only or not
+    pub fn insert(&self, world: &mut World, entity: Entity, value: Converted) {
+        (self.insert)(world, entity, value)
+    }
+}
+
+fn get_component<C: Component + Reflect>(world: &World, entity: Entity) -> Option<Value> {
+    world.get::<C>(entity).map(Persist::to_value)
+}
+
+fn insert_component<C: Component + Reflect>(world: &mut World, entity: Entity, value: Converted) {
+    world.insert(entity, downcast::<C>(value));
+}
+
+fn remove_component<C: Component + Reflect>(world: &mut World, entity: Entity) -> bool {
+    world.remove::<C>(entity).is_some()
+}
+
+/// A registered Resource type, Resources only take part in snapshots
+#[derive(Clone)]
+pub struct ResourceRegistration {
+    name: String,
+    type_id: TypeId,
+    get: fn(&World) -> Option<Value>,
+    convert: fn(&Value) -> Result<Converted, ValueError>,
+    insert: fn(&mut World, Converted),
+}
+
+impl ResourceRegistration {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn type_id(&self) -> TypeId {
+        self.type_id
+    }
+
+    pub fn get(&self, world: &World) -> Option<Value> {
+        (self.get)(world)
+    }
+
+    pub fn convert(&self, value: &Value) -> Result<Converted, ValueError> {
+        (self.convert)(value)
+    }
+
+    /// Inserts a value returned by `convert` of this registration
+    pub fn insert(&self, world: &mut World, value: Converted) {
+        (self.insert)(world, value)
+    }
+}
+
+fn get_resource<R: Resource + Reflect>(world: &World) -> Option<Value> {
+    world.resource::<R>().map(Persist::to_value)
+}
+
+fn insert_resource<R: Resource + Reflect>(world: &mut World, value: Converted) {
+    world.insert_resource(downcast::<R>(value));
+}
+
+/// Reflected Component and Resource types, looked up by registered name or TypeId
+///
+/// The names are what ties a snapshot to the types, so they have to stay the same for old save files
+/// to keep loading. Components and Resources have names of their own
+#[derive(Clone, Default)]
+pub struct TypeRegistry {
+    registrations: Vec<TypeRegistration>,
+    names: HashMap<String, usize>,
+    type_ids: HashMap<TypeId, usize>,
+    resources: Vec<ResourceRegistration>,
+    resource_names: HashMap<String, usize>,
+}
+
+impl TypeRegistry {
+    /// Returns false if the name or the type is already registered, in which case nothing changes
+    pub fn register<C: Component + Reflect>(&mut self, name: &str) -> bool {
+        let type_id = TypeId::of::<C>();
+        if self.names.contains_key(name) || self.type_ids.contains_key(&type_id) {
+            return false;
+        }
+        self.names.insert(name.to_string(), self.registrations.len());
+        self.type_ids.insert(type_id, self.registrations.len());
+        self.registrations.push(TypeRegistration {
+            name: name.to_string(),
+            type_name: type_name::<C>(),
+            type_id,
+            size: size_of::<C>(),
+            fields: C::fields(),
+            read_only: C::read_only(),
+            get: get_component::<C>,
+            convert: convert::<C>,
+            insert: insert_component::<C>,
+            remove: remove_component::<C>,
+        });
+        true
+    }
+
+    /// Returns false if the name or the type is already registered, in which case nothing changes
+    pub fn register_resource<R: Resource + Reflect>(&mut self, name: &str) -> bool {
+        let type_id = TypeId::of::<R>();
+        if self.resource_names.contains_key(name) || self.resources.iter().any(|resource| resource.type_id == type_id) {
+            return false;
+        }
+        self.resource_names.insert(name.to_string(), self.resources.len());
+        self.resources.push(ResourceRegistration {
+            name: name.to_string(),
+            type_id,
+            get: get_resource::<R>,
+            convert: convert::<R>,
+            insert: insert_resource::<R>,
+        });
+        true
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TypeRegistration> {
+        self.names.get(name).map(|&index| &self.registrations[index])
+    }
+
+    pub fn get_by_type_id(&self, type_id: TypeId) -> Option<&TypeRegistration> {
+        self.type_ids.get(&type_id).map(|&index| &self.registrations[index])
+    }
+
+    pub fn get_resource(&self, name: &str) -> Option<&ResourceRegistration> {
+        self.resource_names.get(name).map(|&index| &self.resources[index])
+    }
+
+    /// Number of Component registrations
+    pub fn len(&self) -> usize {
+        self.registrations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registrations.is_empty()
+    }
+
+    /// Every Component registration in the order they were made
+    pub fn iter(&self) -> impl Iterator<Item = &TypeRegistration> {
+        self.registrations.iter()
+    }
+
+    /// Every Resource registration in the order they were made
+    pub fn resources(&self) -> impl Iterator<Item = &ResourceRegistration> {
+        self.resources.iter()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReflectError {
+    /// Nothing was registered under the name
+    UnknownType(String),
+    NoSuchEntity(Entity),
+    /// The Entity exists but does not have the Component
+    MissingComponent { entity: Entity, name: String },
+    /// The Component's Value has no field of that name
+    NoSuchField { name: String, field: String },
+    /// The type is read-only, see `Reflect::read_only`
+    ReadOnly(String),
+    /// The Value does not fit the registered type
+    Value(ValueError),
+}
+
+impl fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReflectError::UnknownType(name) => write!(f, "no type registered as {}", name),
+            ReflectError::NoSuchEntity(entity) => write!(f, "{} does not exist", entity),
+            ReflectError::MissingComponent { entity, name } => write!(f, "{} has no {}", entity, name),
+            ReflectError::NoSuchField { name, field } => write!(f, "{} has no field {}", name, field),
+            ReflectError::ReadOnly(name) => write!(f, "{} is read-only", name),
+            ReflectError::Value(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for ReflectError {}
+
+impl From<ValueError> for ReflectError {
+    fn from(error: ValueError) -> ReflectError {
+        ReflectError::Value(error)
+    }
+}
+
+impl World {
+    /// Makes C available to the reflection methods under `name`
+    ///
+    /// Returns false if the name or the type is already registered, in which case nothing changes
+    pub fn register_type<C: Component + Reflect>(&mut self, name: &str) -> bool {
+        self.init_component::<C>();
+        self.type_registry_mut().register::<C>(name)
+    }
+
+    /// Makes R part of snapshots under `name`, see `register_type`
+    pub fn register_resource_type<R: Resource + Reflect>(&mut self, name: &str) -> bool {
+        self.type_registry_mut().register_resource::<R>(name)
+    }
+
+    /// The registered Components the Entity has, in no particular order
+    pub fn reflect_components(&self, entity: Entity) -> Result<Vec<&TypeRegistration>, ReflectError> {
+        let location = self.location(entity).ok_or(ReflectError::NoSuchEntity(entity))?;
+        let registry = self.type_registry();
+        Ok(self
+            .archetypes()
+            .get(location.archetype)
+            .components()
+            .iter()
+            .filter_map(|&id| registry.get_by_type_id(self.components().info(id).type_id()))
+            .collect())
+    }
+
+    /// A copy of the Component registered as `name`
+    pub fn get_reflect(&self, entity: Entity, name: &str) -> Result<Value, ReflectError> {
+        let registration = self.registration(entity, name)?;
+        registration.get(self, entity).ok_or_else(|| ReflectError::MissingComponent {
+            entity,
+            name: name.to_string(),
+        })
+    }
+
+    /// Inserts the Component registered as `name` or replaces the one the Entity has
+    pub fn set_reflect(&mut self, entity: Entity, name: &str, value: &Value) -> Result<(), ReflectError> {
+        let registration = self.writable_registration(entity, name)?;
+        let insert = registration.insert;
+        let value = registration.convert(value)?;
+        insert(self, entity, value);
+        Ok(())
+    }
+
+    /// Removes the Component registered as `name`, returns false if the Entity did not have it
+    pub fn remove_reflect(&mut self, entity: Entity, name: &str) -> Result<bool, ReflectError> {
+        let remove = self.writable_registration(entity, name)?.remove;
+        Ok(remove(self, entity))
+    }
+
+    pub fn get_field(&self, entity: Entity, name: &str, field: &str) -> Result<Value, ReflectError> {
+        self.get_reflect(entity, name)?
+            .field(field)
+            .cloned()
+            .ok_or_else(|| ReflectError::NoSuchField {
+                name: name.to_string(),
+                field: field.to_string(),
+            })
+    }
+
+    /// Writes a single field of a Component the Entity already has, marking it changed
+    pub fn set_field(&mut self, entity: Entity, name: &str, field: &str, value: Value) -> Result<(), ReflectError> {
+        self.writable_registration(entity, name)?;
+        let mut component = self.get_reflect(entity, name)?;
+        match component.field_mut(field) {
+            Some(slot) => *slot = value,
+            None => {
+                return Err(ReflectError::NoSuchField {
+                    name: name.to_string(),
+                    field: field.to_string(),
+                })
+            }
+        }
+        self.set_reflect(entity, name, &component)
+    }
+
+    fn registration(&self, entity: Entity, name: &str) -> Result<&TypeRegistration, ReflectError> {
+        if !self.contains(entity) {
+            return Err(ReflectError::NoSuchEntity(entity));
+        }
+        self.type_registry()
+            .get(name)
+            .ok_or_else(|| ReflectError::UnknownType(name.to_string()))
+    }
+
+    fn writable_registration(&self, entity: Entity, name: &str) -> Result<&TypeRegistration, ReflectError> {
+        let registration = self.registration(entity, name)?;
+        if registration.read_only {
+            return Err(ReflectError::ReadOnly(name.to_string()));
+        }
+        Ok(registration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::entity::hierarchy::{ Children, Parent };
+
+    #[derive(Debug, PartialEq)]
+    struct Health {
+        current: u32,
+        max: u32,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Name {
+        name: String,
+    }
+
+    crate::reflect_struct!(Health { current: u32, max: u32 });
+    crate::reflect_struct!(Name { name: String });
+
+    fn health(current: u32, max: u32) -> Value {
+        Health { current, max }.to_value()
+    }
+
+    fn world() -> World {
+        let mut world = World::new();
+        assert!(world.register_type::<Health>("Health"));
+        assert!(world.register_type::<Name>("Name"));
+        assert!(world.register_type::<Parent>("Parent"));
+        assert!(!world.register_type::<Health>("Other"));
+        assert!(!world.register_type::<Children>("Name"));
+        world
+    }
+
+    #[test]
+    fn registrations_describe_the_type() {
+        let world = world();
+        let registry = world.type_registry();
+        assert_eq!(registry.len(), 3);
+        assert_eq!(registry.iter().map(TypeRegistration::name).collect::<Vec<_>>(), vec!["Health", "Name", "Parent"]);
+
+        let registration = registry.get_by_type_id(TypeId::of::<Health>()).unwrap();
+        assert_eq!(registration.name(), "Health");
+        assert!(registration.type_name().ends_with("Health"));
+        assert_eq!(registration.size(), 8);
+        assert_eq!(registration.field("max"), Some(&FieldInfo { name: "max", type_name: "u32" }));
+        assert!(registration.field("min").is_none());
+        assert!(!registration.is_read_only());
+        assert!(registry.get("Parent").unwrap().is_read_only());
+    }
+
+    #[test]
+    fn components_are_read_and_written_by_name() {
+        let mut world = world();
+        let entity = world.spawn((Health { current: 3, max: 10 },));
+
+        assert_eq!(world.get_reflect(entity, "Health"), Ok(health(3, 10)));
+        assert_eq!(world.get_field(entity, "Health", "max"), Ok(Value::Int(10)));
+        world.set_field(entity, "Health", "current", Value::Int(7)).unwrap();
+        assert_eq!(world.get::<Health>(entity), Some(&Health { current: 7, max: 10 }));
+
+        world.set_reflect(entity, "Name", &Name { name: "orc".to_string() }.to_value()).unwrap();
+        let mut names: Vec<_> = world.reflect_components(entity).unwrap().iter().map(|r| r.name()).collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["Health", "Name"]);
+
+        assert_eq!(world.remove_reflect(entity, "Name"), Ok(true));
+        assert_eq!(world.remove_reflect(entity, "Name"), Ok(false));
+        assert!(world.get::<Name>(entity).is_none());
+    }
+
+    #[test]
+    fn mistakes_are_reported() {
+        let mut world = world();
+        let entity = world.spawn((Health { current: 3, max: 10 },));
+        let gone = world.spawn(());
+        world.despawn(gone);
+
+        assert_eq!(world.get_reflect(entity, "Mana"), Err(ReflectError::UnknownType("Mana".to_string())));
+        assert_eq!(world.get_reflect(gone, "Health"), Err(ReflectError::NoSuchEntity(gone)));
+        assert_eq!(
+            world.get_reflect(entity, "Name"),
+            Err(ReflectError::MissingComponent { entity, name: "Name".to_string() })
+        );
+        assert_eq!(
+            world.set_field(entity, "Health", "min", Value::Int(0)),
+            Err(ReflectError::NoSuchField { name: "Health".to_string(), field: "min".to_string() })
+        );
+        assert!(matches!(world.set_reflect(entity, "Health", &Value::Int(1)), Err(ReflectError::Value(_))));
+        assert_eq!(world.get::<Health>(entity), Some(&Health { current: 3, max: 10 }));
+    }
+
+    #[test]
+    fn hierarchy_components_are_read_only() {
+        let mut world = world();
+        let parent = world.spawn(());
+        let other = world.spawn(());
+        let child = world.spawn(());
+        world.attach(child, parent).unwrap();
+
+        assert_eq!(world.get_reflect(child, "Parent"), Ok(Value::Entity(parent)));
+        let read_only = Err(ReflectError::ReadOnly("Parent".to_string()));
+        assert_eq!(world.set_reflect(child, "Parent", &Value::Entity(other)), read_only);
+        assert_eq!(world.remove_reflect(child, "Parent").map(|_| ()), read_only);
+        assert_eq!(world.parent(child), Ok(Some(parent)));
+        assert_eq!(world.children(parent), &[child]);
+    }
+}
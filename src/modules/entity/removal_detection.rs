@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use super::component::{ Component, ComponentId };
+use super::entity_map::Entity;
+use super::event::{ EventIter, Events, ManualEventReader };
+use super::system::{ SystemMeta, SystemParam };
+use super::world::World;
+
+/// Which Entities lost which Components, and which were despawned altogether
+///
+/// Kept as Events so every reader sees each removal once, as long as it runs at least once every two
+/// calls to `World::clear_trackers`
+#[derive(Default)]
+pub struct RemovedComponentEvents {
+    components: HashMap<ComponentId, Events<Entity>>,
+    despawned: Events<Entity>,
+}
+
+impl RemovedComponentEvents {
+    /// Records that the Entity lost the Component, whether it was removed or the Entity despawned
+    pub fn send(&mut self, component: ComponentId, entity: Entity) {
+        self.components.entry(component).or_default().send(entity);
+    }
+
+    pub fn send_despawned(&mut self, entity: Entity) {
+        self.despawned.send(entity);
+    }
+
+    pub fn get(&self, component: ComponentId) -> Option<&Events<Entity>> {
+        self.components.get(&component)
+    }
+
+    pub fn get_or_init(&mut self, component: ComponentId) -> &Events<Entity> {
+        self.components.entry(component).or_default()
+    }
+
+    pub fn despawned(&self) -> &Events<Entity> {
+        &self.despawned
+    }
+
+    pub fn update(&mut self) {
+        for events in self.components.values_mut() {
+            events.update();
+        }
+        self.despawned.update();
+    }
+}
+
+/// System parameter listing the Entities that lost T since the System last ran, either because T was
+/// removed or because the Entity was despawned
+///
+/// Removals need `&mut World`, so this never conflicts with any other System
+pub struct RemovedComponents<'w, 's, T: Component> {
+    reader: &'s mut ManualEventReader<Entity>,
+    events: &'w Events<Entity>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'w, 's, T: Component> RemovedComponents<'w, 's, T> {
+    /// Every Entity that lost T since the last call, `missed` on the iterator counts the ones dropped
+    /// before this System got to see them
+    pub fn read(&mut self) -> EventIter<'_, Entity> {
+        self.reader.read(self.events)
+    }
+
+    pub fn len(&self) -> usize {
+        self.reader.len(self.events)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reader.is_empty(self.events)
+    }
+
+    pub fn clear(&mut self) {
+        self.reader.clear(self.events);
+    }
+}
+
+unsafe impl<'a, 'b, T: Component> SystemParam for RemovedComponents<'a, 'b, T> {
+    type State = (ComponentId, ManualEventReader<Entity>);
+    type Item<'w, 's> = RemovedComponents<'w, 's, T>;
+
+    fn init_state(world: &mut World, _meta: &mut SystemMeta) -> Self::State {
+        let component = world.init_component::<T>();
+        let reader = world.removed_components_mut().get_or_init(component).get_reader();
+        (component, reader)
+    }
+
+    unsafe fn get_param<'w, 's>(state: &'s mut Self::State, _meta: &SystemMeta, world: &'w World) -> Self::Item<'w, 's> {
+        let (component, reader) = state;
+        RemovedComponents {
+            reader,
+            events: world
+                .removed_components()
+                .get(*component)
+                .expect("removal events dropped after the system was initialized"),
+            marker: PhantomData,
+        }
+    }
+}
+
+/// System parameter listing the Entities despawned since the System last ran
+pub struct RemovedEntities<'w, 's> {
+    reader: &'s mut ManualEventReader<Entity>,
+    events: &'w Events<Entity>,
+}
+
+impl<'w, 's> RemovedEntities<'w, 's> {
+    /// Every Entity despawned since the last call, see `RemovedComponents::read`
+    pub fn read(&mut self) -> EventIter<'_, Entity> {
+        self.reader.read(self.events)
+    }
+
+    pub fn len(&self) -> usize {
+        self.reader.len(self.events)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reader.is_empty(self.events)
+    }
+
+    pub fn clear(&mut self) {
+        self.reader.clear(self.events);
+    }
+}
+
+unsafe impl<'a, 'b> SystemParam for RemovedEntities<'a, 'b> {
+    type State = ManualEventReader<Entity>;
+    type Item<'w, 's> = RemovedEntities<'w, 's>;
+
+    fn init_state(world: &mut World, _meta: &mut SystemMeta) -> ManualEventReader<Entity> {
+        world.removed_components().despawned().get_reader()
+    }
+
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut ManualEventReader<Entity>,
+        _meta: &SystemMeta,
+        world: &'w World,
+    ) -> Self::Item<'w, 's> {
+        RemovedEntities {
+            reader: state,
+            events: world.removed_components().despawned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::entity::resource::ResMut;
+    use crate::modules::entity::system::{ IntoSystem, System };
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct Armor(u32);
+
+    #[derive(Default)]
+    struct Seen {
+        removed: Vec<Entity>,
+        despawned: Vec<Entity>,
+        missed: usize,
+    }
+
+    fn watch(mut removed: RemovedComponents<Health>, mut despawned: RemovedEntities, mut seen: ResMut<Seen>) {
+        let iter = removed.read();
+        seen.missed = iter.missed();
+        seen.removed = iter.copied().collect();
+        seen.despawned = despawned.read().copied().collect();
+    }
+
+    fn world() -> (World, impl System) {
+        let mut world = World::new();
+        world.insert_resource(Seen::default());
+        let mut system = watch.into_system();
+        system.initialize(&mut world);
+        (world, system)
+    }
+
+    #[test]
+    fn removals_and_despawns_are_reported_once() {
+        let (mut world, mut system) = world();
+        let removed = world.spawn((Health(1), Armor(1)));
+        let despawned = world.spawn((Health(2),));
+        let unrelated = world.spawn((Armor(3),));
+
+        assert_eq!(world.remove::<Health>(removed), Some(Health(1)));
+        world.despawn(despawned);
+        world.despawn(unrelated);
+        system.run(&mut world);
+        let seen = world.resource::<Seen>().unwrap();
+        assert_eq!(seen.removed, vec![removed, despawned]);
+        assert_eq!(seen.despawned, vec![despawned, unrelated]);
+
+        system.run(&mut world);
+        let seen = world.resource::<Seen>().unwrap();
+        assert!(seen.removed.is_empty());
+        assert!(seen.despawned.is_empty());
+    }
+
+    #[test]
+    fn removals_are_kept_for_two_frames() {
+        let (mut world, mut system) = world();
+        let first = world.spawn((Health(1),));
+        let second = world.spawn((Health(2),));
+
+        world.remove::<Health>(first);
+        world.clear_trackers();
+        system.run(&mut world);
+        assert_eq!(world.resource::<Seen>().unwrap().removed, vec![first]);
+
+        world.remove::<Health>(second);
+        world.clear_trackers();
+        world.clear_trackers();
+        system.run(&mut world);
+        let seen = world.resource::<Seen>().unwrap();
+        assert!(seen.removed.is_empty());
+        assert_eq!(seen.missed, 1);
+    }
+}
@@ -0,0 +1,229 @@
+use std::any::{ type_name, Any, TypeId };
+use std::collections::HashMap;
+use std::ops::{ Deref, DerefMut };
+
+use super::storage::SyncCell;
+use super::system::{ SystemMeta, SystemParam };
+use super::world::World;
+
+/// World level singleton, e.g. frame time, input state or the window size
+pub trait Resource: Send + Sync + 'static {}
+
+impl<T: Send + Sync + 'static> Resource for T {}
+
+struct ResourceData {
+    value: SyncCell<Box<dyn Any + Send + Sync>>,
+}
+
+/// Every Resource in the World, at most one per type
+#[derive(Default)]
+pub struct Resources {
+    resources: HashMap<TypeId, ResourceData>,
+}
+
+impl Resources {
+    pub fn insert<R: Resource>(&mut self, value: R) -> Option<R> {
+        let old = self.resources.insert(TypeId::of::<R>(), ResourceData {
+            value: SyncCell::new(Box::new(value)),
+        });
+        old.map(|data| *data.value.into_inner().downcast::<R>().expect("resource stored under the wrong type"))
+    }
+
+    pub fn remove<R: Resource>(&mut self) -> Option<R> {
+        let data = self.resources.remove(&TypeId::of::<R>())?;
+        Some(*data.value.into_inner().downcast::<R>().expect("resource stored under the wrong type"))
+    }
+
+    pub fn contains<R: Resource>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<R>())
+    }
+
+    pub fn get<R: Resource>(&self) -> Option<&R> {
+        let data = self.resources.get(&TypeId::of::<R>())?;
+        // Mutable access through `&self` only happens via ResMut, whose access is checked
+        let value = unsafe { data.value.deref() };
+        value.downcast_ref::<R>()
+    }
+
+    pub fn get_mut<R: Resource>(&mut self) -> Option<&mut R> {
+        let data = self.resources.get_mut(&TypeId::of::<R>())?;
+        data.value.get_mut().downcast_mut::<R>()
+    }
+
+    /// # Safety
+    /// The caller has to hold write access to R for as long as the reference lives
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_unchecked_mut<R: Resource>(&self) -> Option<&mut R> {
+        let data = self.resources.get(&TypeId::of::<R>())?;
+        (*data.value.get()).downcast_mut::<R>()
+    }
+}
+
+/// System parameter borrowing the Resource R, the System panics if it does not exist
+pub struct Res<'w, R: Resource> {
+    value: &'w R,
+}
+
+impl<'w, R: Resource> Deref for Res<'w, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        self.value
+    }
+}
+
+/// System parameter mutably borrowing the Resource R, the System panics if it does not exist
+pub struct ResMut<'w, R: Resource> {
+    value: &'w mut R,
+}
+
+impl<'w, R: Resource> Deref for ResMut<'w, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        self.value
+    }
+}
+
+impl<'w, R: Resource> DerefMut for ResMut<'w, R> {
+    fn deref_mut(&mut self) -> &mut R {
+        self.value
+    }
+}
+
+unsafe impl<'a, R: Resource> SystemParam for Res<'a, R> {
+    type State = ();
+    type Item<'w, 's> = Res<'w, R>;
+
+    fn init_state(_world: &mut World, meta: &mut SystemMeta) {
+        meta.access_mut().add_resource_read(TypeId::of::<R>(), type_name::<R>());
+    }
+
+    unsafe fn get_param<'w, 's>(_state: &'s mut (), meta: &SystemMeta, world: &'w World) -> Self::Item<'w, 's> {
+        match world.resource::<R>() {
+            Some(value) => Res { value },
+            None => panic!("resource {} requested by {} does not exist", type_name::<R>(), meta.name()),
+        }
+    }
+}
+
+unsafe impl<'a, R: Resource> SystemParam for ResMut<'a, R> {
+    type State = ();
+    type Item<'w, 's> = ResMut<'w, R>;
+
+    fn init_state(_world: &mut World, meta: &mut SystemMeta) {
+        meta.access_mut().add_resource_write(TypeId::of::<R>(), type_name::<R>());
+    }
+
+    unsafe fn get_param<'w, 's>(_state: &'s mut (), meta: &SystemMeta, world: &'w World) -> Self::Item<'w, 's> {
+        match world.resources().get_unchecked_mut::<R>() {
+            Some(value) => ResMut { value },
+            None => panic!("resource {} requested by {} does not exist", type_name::<R>(), meta.name()),
+        }
+    }
+}
+
+unsafe impl<'a, R: Resource> SystemParam for Option<Res<'a, R>> {
+    type State = ();
+    type Item<'w, 's> = Option<Res<'w, R>>;
+
+    fn init_state(_world: &mut World, meta: &mut SystemMeta) {
+        meta.access_mut().add_resource_read(TypeId::of::<R>(), type_name::<R>());
+    }
+
+    unsafe fn get_param<'w, 's>(_state: &'s mut (), _meta: &SystemMeta, world: &'w World) -> Self::Item<'w, 's> {
+        world.resource::<R>().map(|value| Res { value })
+    }
+}
+
+unsafe impl<'a, R: Resource> SystemParam for Option<ResMut<'a, R>> {
+    type State = ();
+    type Item<'w, 's> = Option<ResMut<'w, R>>;
+
+    fn init_state(_world: &mut World, meta: &mut SystemMeta) {
+        meta.access_mut().add_resource_write(TypeId::of::<R>(), type_name::<R>());
+    }
+
+    unsafe fn get_param<'w, 's>(_state: &'s mut (), _meta: &SystemMeta, world: &'w World) -> Self::Item<'w, 's> {
+        world.resources().get_unchecked_mut::<R>().map(|value| ResMut { value })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::entity::system::{ IntoSystem, System };
+
+    #[derive(Debug, PartialEq)]
+    struct Time(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct Score(u32);
+
+    fn run<Marker>(world: &mut World, system: impl IntoSystem<Marker>) {
+        let mut system = system.into_system();
+        system.initialize(world);
+        system.run(world);
+    }
+
+    #[test]
+    fn resources_are_stored_once_per_type() {
+        let mut world = World::new();
+        assert_eq!(world.insert_resource(Time(1)), None);
+        assert_eq!(world.insert_resource(Time(2)), Some(Time(1)));
+        world.insert_resource(Score(0));
+        world.resource_mut::<Score>().unwrap().0 += 5;
+
+        assert!(world.contains_resource::<Time>());
+        assert_eq!(world.resource::<Time>(), Some(&Time(2)));
+        assert_eq!(world.resource::<Score>(), Some(&Score(5)));
+        assert_eq!(world.remove_resource::<Time>(), Some(Time(2)));
+        assert_eq!(world.resource::<Time>(), None);
+        assert!(!world.contains_resource::<Time>());
+    }
+
+    #[test]
+    fn systems_read_and_write_resources() {
+        let mut world = World::new();
+        world.insert_resource(Time(3));
+        world.insert_resource(Score(1));
+        run(&mut world, |time: Res<Time>, mut score: ResMut<Score>| score.0 += time.0);
+        run(&mut world, |time: Option<Res<Time>>, score: Option<ResMut<Score>>| {
+            assert_eq!(time.map(|time| time.0), Some(3));
+            assert!(score.is_some());
+        });
+        world.remove_resource::<Time>();
+        run(&mut world, |time: Option<Res<Time>>| assert!(time.is_none()));
+        assert_eq!(world.resource::<Score>(), Some(&Score(4)));
+    }
+
+    #[test]
+    #[should_panic(expected = "does not exist")]
+    fn missing_resources_panic() {
+        let mut world = World::new();
+        run(&mut world, |_: Res<Time>| {});
+    }
+
+    #[test]
+    #[should_panic(expected = "would alias a &mut")]
+    fn aliasing_resources_panic() {
+        let mut world = World::new();
+        world.insert_resource(Time(0));
+        run(&mut world, |_: Res<Time>, _: ResMut<Time>| {});
+    }
+
+    #[test]
+    fn resource_access_decides_compatibility() {
+        let mut world = World::new();
+        let mut reader = (|_: Res<Time>| {}).into_system();
+        let mut other_reader = (|_: Res<Time>, _: ResMut<Score>| {}).into_system();
+        let mut writer = (|_: ResMut<Time>| {}).into_system();
+        reader.initialize(&mut world);
+        other_reader.initialize(&mut world);
+        writer.initialize(&mut world);
+
+        assert!(reader.access().is_compatible(other_reader.access()));
+        assert!(!reader.access().is_compatible(writer.access()));
+        assert!(!writer.access().is_compatible(reader.access()));
+    }
+}
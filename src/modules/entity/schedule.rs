@@ -0,0 +1,586 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use super::change_detection::{ Tick, CHECK_TICK_THRESHOLD };
+use super::event::{ Event, Events };
+use super::executor::{ self, ExecutorKind, SystemGraph, ThreadPool };
+use super::system::{ IntoSystem, System };
+use super::transform;
+use super::world::World;
+
+/// The stages every Schedule starts out with, in the order they run
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CoreStage {
+    /// Only runs the first time the Schedule is run
+    Startup,
+    PreUpdate,
+    Update,
+    PostUpdate,
+    Render,
+}
+
+impl CoreStage {
+    pub const ALL: [CoreStage; 5] = [
+        CoreStage::Startup,
+        CoreStage::PreUpdate,
+        CoreStage::Update,
+        CoreStage::PostUpdate,
+        CoreStage::Render,
+    ];
+}
+
+impl AsRef<str> for CoreStage {
+    fn as_ref(&self) -> &str {
+        match self {
+            CoreStage::Startup => "startup",
+            CoreStage::PreUpdate => "pre_update",
+            CoreStage::Update => "update",
+            CoreStage::PostUpdate => "post_update",
+            CoreStage::Render => "render",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScheduleError {
+    /// The before/after constraints in a stage loop back on themselves, `systems` lists the loop in
+    /// order with the first System repeated at the end
+    Cycle { stage: String, systems: Vec<String> },
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::Cycle { stage, systems } => {
+                write!(f, "ordering cycle in stage {}: {}", stage, systems.join(" -> "))
+            }
+        }
+    }
+}
+
+impl Error for ScheduleError {}
+
+/// A System along with the labels it goes by and the labels it has to run before or after
+pub struct SystemDescriptor {
+    system: Box<dyn System>,
+    labels: Vec<&'static str>,
+    before: Vec<&'static str>,
+    after: Vec<&'static str>,
+}
+
+impl SystemDescriptor {
+    pub fn system(&self) -> &dyn System {
+        &*self.system
+    }
+
+    pub fn labels(&self) -> &[&'static str] {
+        &self.labels
+    }
+
+    /// The first label if there is one, otherwise the System's name
+    pub fn display_name(&self) -> String {
+        match self.labels.first() {
+            Some(label) => label.to_string(),
+            None => self.system.name().into_owned(),
+        }
+    }
+}
+
+/// Marker for the IntoSystemDescriptor impl of descriptors themselves
+pub struct IsDescriptor;
+
+/// Lets ordering be attached straight to a System or function, e.g. `movement.label("movement")`
+pub trait IntoSystemDescriptor<Marker>: Sized {
+    fn into_descriptor(self) -> SystemDescriptor;
+
+    fn label(self, label: &'static str) -> SystemDescriptor {
+        let mut descriptor = self.into_descriptor();
+        descriptor.labels.push(label);
+        descriptor
+    }
+
+    /// Runs before every System in the same stage labelled `label`
+    fn before(self, label: &'static str) -> SystemDescriptor {
+        let mut descriptor = self.into_descriptor();
+        descriptor.before.push(label);
+        descriptor
+    }
+
+    /// Runs after every System in the same stage labelled `label`
+    fn after(self, label: &'static str) -> SystemDescriptor {
+        let mut descriptor = self.into_descriptor();
+        descriptor.after.push(label);
+        descriptor
+    }
+}
+
+impl IntoSystemDescriptor<IsDescriptor> for SystemDescriptor {
+    fn into_descriptor(self) -> SystemDescriptor {
+        self
+    }
+}
+
+impl<Marker, S: IntoSystem<Marker>> IntoSystemDescriptor<Marker> for S {
+    fn into_descriptor(self) -> SystemDescriptor {
+        SystemDescriptor {
+            system: Box::new(self.into_system()),
+            labels: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+}
+
+/// A group of Systems that run together, ordered by their before/after constraints and otherwise in
+/// the order they were added
+///
+/// By default Systems whose access does not conflict run in parallel, deferred work is applied
+/// once every System in the stage has finished
+#[derive(Default)]
+pub struct Stage {
+    systems: Vec<SystemDescriptor>,
+    order: Vec<usize>,
+    graph: SystemGraph,
+    initialized: usize,
+    dirty: bool,
+    executor: ExecutorKind,
+    threads: Option<usize>,
+    /// Made on the first multi threaded run unless a Schedule handed one over
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl Stage {
+    pub fn new() -> Stage {
+        Stage::default()
+    }
+
+    pub fn set_executor(&mut self, executor: ExecutorKind) -> &mut Stage {
+        self.executor = executor;
+        self
+    }
+
+    /// Caps how many Systems the MultiThreaded executor runs at once, defaults to the available cores
+    ///
+    /// The pool's size is the upper limit, see `set_thread_pool`
+    pub fn set_threads(&mut self, threads: usize) -> &mut Stage {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// The worker threads the MultiThreaded executor runs Systems on
+    pub fn set_thread_pool(&mut self, pool: Arc<ThreadPool>) -> &mut Stage {
+        self.pool = Some(pool);
+        self
+    }
+
+    pub fn thread_pool(&self) -> Option<&Arc<ThreadPool>> {
+        self.pool.as_ref()
+    }
+
+    pub fn add_system<M>(&mut self, system: impl IntoSystemDescriptor<M>) -> &mut Stage {
+        self.systems.push(system.into_descriptor());
+        self.dirty = true;
+        self
+    }
+
+    pub fn systems(&self) -> &[SystemDescriptor] {
+        &self.systems
+    }
+
+    /// Indices into `systems` in the order they will run, only valid after `initialize`
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+
+    /// Sets up Systems added since the last call and works out the run order
+    pub fn initialize(&mut self, name: &str, world: &mut World) -> Result<(), ScheduleError> {
+        for descriptor in self.systems[self.initialized..].iter_mut() {
+            descriptor.system.initialize(world);
+        }
+        self.initialized = self.systems.len();
+
+        if self.dirty {
+            let dependencies = self.dependencies();
+            self.order = self.sort(name, &dependencies)?;
+            let systems: Vec<&dyn System> = self.systems.iter().map(|descriptor| &*descriptor.system).collect();
+            self.graph = SystemGraph::new(&systems, &self.order, &dependencies);
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Edges of the ordering graph, `dependencies[i]` holds every System that has to run before `i`
+    pub fn dependencies(&self) -> Vec<Vec<usize>> {
+        let mut labelled: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, descriptor) in self.systems.iter().enumerate() {
+            for label in descriptor.labels.iter() {
+                labelled.entry(label).or_default().push(index);
+            }
+        }
+
+        // Labels nobody in this stage carries are ignored
+        let mut dependencies = vec![Vec::new(); self.systems.len()];
+        for (index, descriptor) in self.systems.iter().enumerate() {
+            for label in descriptor.after.iter() {
+                for &other in labelled.get(label).into_iter().flatten() {
+                    dependencies[index].push(other);
+                }
+            }
+            for label in descriptor.before.iter() {
+                for &other in labelled.get(label).into_iter().flatten() {
+                    dependencies[other].push(index);
+                }
+            }
+        }
+        for edges in dependencies.iter_mut() {
+            edges.sort_unstable();
+            edges.dedup();
+        }
+        dependencies
+    }
+
+    /// Kahn's algorithm, always picking the earliest added System that is ready so unconstrained
+    /// Systems keep their insertion order
+    fn sort(&self, name: &str, dependencies: &[Vec<usize>]) -> Result<Vec<usize>, ScheduleError> {
+        let mut dependents = vec![Vec::new(); self.systems.len()];
+        let mut remaining: Vec<usize> = dependencies.iter().map(Vec::len).collect();
+        for (index, edges) in dependencies.iter().enumerate() {
+            for &dependency in edges {
+                dependents[dependency].push(index);
+            }
+        }
+
+        let mut order = Vec::with_capacity(self.systems.len());
+        let mut done = vec![false; self.systems.len()];
+        while order.len() < self.systems.len() {
+            let next = (0..self.systems.len()).find(|&i| !done[i] && remaining[i] == 0);
+            let next = match next {
+                Some(next) => next,
+                None => return Err(self.cycle_error(name, dependencies, &done)),
+            };
+
+            done[next] = true;
+            order.push(next);
+            for &dependent in dependents[next].iter() {
+                remaining[dependent] -= 1;
+            }
+        }
+        Ok(order)
+    }
+
+    /// Walks dependencies from any unsorted System until one repeats, that loop is the cycle
+    fn cycle_error(&self, name: &str, dependencies: &[Vec<usize>], done: &[bool]) -> ScheduleError {
+        let start = done.iter().position(|done| !done).expect("a cycle needs an unsorted system");
+        let mut path = vec![start];
+        let mut current = start;
+        loop {
+            current = *dependencies[current]
+                .iter()
+                .find(|&&dependency| !done[dependency])
+                .expect("every unsorted system waits on another unsorted system");
+            if let Some(position) = path.iter().position(|&index| index == current) {
+                // `path` runs against the dependency edges, flip it to read in run order
+                let mut cycle: Vec<String> = path[position..]
+                    .iter()
+                    .rev()
+                    .map(|&index| self.systems[index].display_name())
+                    .collect();
+                cycle.push(cycle[0].clone());
+                return ScheduleError::Cycle {
+                    stage: name.to_string(),
+                    systems: cycle,
+                };
+            }
+            path.push(current);
+        }
+    }
+
+    /// Runs every System once, then applies their deferred work in run order
+    pub fn run(&mut self, name: &str, world: &mut World) -> Result<(), ScheduleError> {
+        self.initialize(name, world)?;
+
+        let mut systems: Vec<&mut dyn System> = self.systems
+            .iter_mut()
+            .map(|descriptor| &mut *descriptor.system)
+            .collect();
+        match self.executor {
+            ExecutorKind::SingleThreaded => executor::run_single_threaded(&mut systems, &self.order, world),
+            ExecutorKind::MultiThreaded => {
+                let threads = self.threads.unwrap_or_else(executor::default_threads);
+                let pool = self.pool.get_or_insert_with(|| Arc::new(ThreadPool::new(executor::default_threads())));
+                executor::run_multi_threaded(&mut systems, &self.graph, world, pool, threads);
+            }
+        }
+
+        for &index in self.order.iter() {
+            self.systems[index].system.apply_deferred(world);
+        }
+        Ok(())
+    }
+}
+
+struct NamedStage {
+    name: Cow<'static, str>,
+    stage: Stage,
+    run_once: bool,
+    has_run: bool,
+}
+
+/// Runs named stages one after the other, this is what drives a frame
+pub struct Schedule {
+    stages: Vec<NamedStage>,
+    last_check_tick: Tick,
+    /// Shared by every stage that does not have a pool of its own
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl Default for Schedule {
+    fn default() -> Schedule {
+        Schedule::new()
+    }
+}
+
+impl Schedule {
+    /// A Schedule with every CoreStage
+    pub fn new() -> Schedule {
+        let mut schedule = Schedule::empty();
+        for stage in CoreStage::ALL.iter() {
+            schedule.add_stage(stage.as_ref().to_string());
+        }
+        schedule.stages[0].run_once = true;
+        schedule
+    }
+
+    pub fn empty() -> Schedule {
+        Schedule {
+            stages: Vec::new(),
+            last_check_tick: Tick::default(),
+            pool: None,
+        }
+    }
+
+    pub fn add_stage(&mut self, name: impl Into<Cow<'static, str>>) -> &mut Schedule {
+        self.insert_stage(self.stages.len(), name.into());
+        self
+    }
+
+    /// Panics if `target` is not a stage of this Schedule
+    pub fn add_stage_before(&mut self, target: impl AsRef<str>, name: impl Into<Cow<'static, str>>) -> &mut Schedule {
+        let index = self.stage_index(target.as_ref());
+        self.insert_stage(index, name.into());
+        self
+    }
+
+    /// Panics if `target` is not a stage of this Schedule
+    pub fn add_stage_after(&mut self, target: impl AsRef<str>, name: impl Into<Cow<'static, str>>) -> &mut Schedule {
+        let index = self.stage_index(target.as_ref()) + 1;
+        self.insert_stage(index, name.into());
+        self
+    }
+
+    fn insert_stage(&mut self, index: usize, name: Cow<'static, str>) {
+        assert!(self.get_stage(&name).is_none(), "stage {} already exists", name);
+        self.stages.insert(index, NamedStage {
+            name,
+            stage: Stage::new(),
+            run_once: false,
+            has_run: false,
+        });
+    }
+
+    fn stage_index(&self, name: &str) -> usize {
+        self.stages
+            .iter()
+            .position(|stage| stage.name == name)
+            .unwrap_or_else(|| panic!("stage {} does not exist", name))
+    }
+
+    pub fn get_stage(&self, name: impl AsRef<str>) -> Option<&Stage> {
+        self.stages.iter().find(|stage| stage.name == name.as_ref()).map(|stage| &stage.stage)
+    }
+
+    pub fn get_stage_mut(&mut self, name: impl AsRef<str>) -> Option<&mut Stage> {
+        self.stages.iter_mut().find(|stage| stage.name == name.as_ref()).map(|stage| &mut stage.stage)
+    }
+
+    /// Switches the executor of every stage
+    pub fn set_executor(&mut self, executor: ExecutorKind) -> &mut Schedule {
+        for stage in self.stages.iter_mut() {
+            stage.stage.set_executor(executor);
+        }
+        self
+    }
+
+    pub fn add_system<M>(&mut self, stage: impl AsRef<str>, system: impl IntoSystemDescriptor<M>) -> &mut Schedule {
+        let index = self.stage_index(stage.as_ref());
+        self.stages[index].stage.add_system(system);
+        self
+    }
+
+    pub fn add_startup_system<M>(&mut self, system: impl IntoSystemDescriptor<M>) -> &mut Schedule {
+        self.add_system(CoreStage::Startup, system)
+    }
+
+    /// Keeps events of type E around for two frames, updating them at the start of every frame
+    pub fn add_event<E: Event>(&mut self) -> &mut Schedule {
+        self.add_system(CoreStage::PreUpdate, Events::<E>::update_system.label("events"))
+    }
+
+    /// Keeps every GlobalTransform in sync with its Transform and those of its ancestors, once per frame
+    pub fn add_transform_propagation(&mut self) -> &mut Schedule {
+        self.add_system(CoreStage::PostUpdate, transform::propagate_transforms.label(transform::PROPAGATE_TRANSFORMS))
+    }
+
+    /// Sets up every stage so ordering problems show up before the first frame
+    pub fn initialize(&mut self, world: &mut World) -> Result<(), ScheduleError> {
+        for stage in self.stages.iter_mut() {
+            stage.stage.initialize(&stage.name, world)?;
+        }
+        Ok(())
+    }
+
+    /// Runs every stage once, in order. Run once stages are skipped after their first run
+    ///
+    /// Every `CHECK_TICK_THRESHOLD` ticks all stored change ticks are clamped, so change detection keeps
+    /// working after the World's change tick wraps around
+    pub fn run(&mut self, world: &mut World) -> Result<(), ScheduleError> {
+        self.share_thread_pool();
+        for stage in self.stages.iter_mut() {
+            if stage.run_once && stage.has_run {
+                continue;
+            }
+            stage.stage.run(&stage.name, world)?;
+            stage.has_run = true;
+        }
+
+        world.clear_trackers();
+        self.check_change_ticks(world);
+        Ok(())
+    }
+
+    /// Hands the Schedule's pool to every multi threaded stage without one, so the threads are spawned
+    /// once for the whole Schedule
+    fn share_thread_pool(&mut self) {
+        let needs_pool = |stage: &NamedStage| stage.stage.executor == ExecutorKind::MultiThreaded && stage.stage.pool.is_none();
+        if !self.stages.iter().any(needs_pool) {
+            return;
+        }
+        let pool = self.pool.get_or_insert_with(|| Arc::new(ThreadPool::new(executor::default_threads())));
+        for stage in self.stages.iter_mut().filter(|stage| needs_pool(stage)) {
+            stage.stage.set_thread_pool(pool.clone());
+        }
+    }
+
+    fn check_change_ticks(&mut self, world: &mut World) {
+        let tick = world.change_tick();
+        if tick.get().wrapping_sub(self.last_check_tick.get()) < CHECK_TICK_THRESHOLD {
+            return;
+        }
+
+        world.check_change_ticks();
+        for stage in self.stages.iter_mut() {
+            for descriptor in stage.stage.systems.iter_mut() {
+                descriptor.system.check_change_tick(tick);
+            }
+        }
+        self.last_check_tick = tick;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::entity::system::Query;
+
+    /// What every test System writes its name into, they all conflict so they never run side by side
+    struct Log(Vec<&'static str>);
+
+    fn push(name: &'static str) -> impl FnMut(Query<&mut Log>) + Send + Sync + 'static {
+        move |mut query: Query<&mut Log>| {
+            for (_, mut log) in query.iter_mut() {
+                log.0.push(name);
+            }
+        }
+    }
+
+    fn log(world: &mut World) -> Vec<&'static str> {
+        let mut query = world.query::<&Log>();
+        query.iter(world).next().map(|(_, log)| log.0.clone()).unwrap()
+    }
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.spawn((Log(Vec::new()),));
+        world
+    }
+
+    #[test]
+    fn systems_run_in_insertion_order_unless_constrained() {
+        let mut world = world();
+        let mut stage = Stage::new();
+        stage
+            .add_system(push("a").label("a"))
+            .add_system(push("b").after("c"))
+            .add_system(push("c").label("c"))
+            .add_system(push("d").before("a"))
+            .add_system(push("e").after("nobody"));
+        stage.run("test", &mut world).unwrap();
+        // The earliest added System that is ready always goes next
+        assert_eq!(log(&mut world), vec!["c", "b", "d", "a", "e"]);
+    }
+
+    #[test]
+    fn ordering_cycles_are_reported_with_the_loop() {
+        let mut world = world();
+        let mut stage = Stage::new();
+        stage
+            .add_system(push("free").label("free"))
+            .add_system(push("a").label("a").after("c"))
+            .add_system(push("b").label("b").after("a"))
+            .add_system(push("c").label("c").after("b"));
+        let error = stage.initialize("update", &mut world).unwrap_err();
+        let ScheduleError::Cycle { stage: name, systems } = &error;
+        assert_eq!(name, "update");
+        assert_eq!(systems.first(), systems.last());
+        assert_eq!(systems.len(), 4);
+        let mut members = systems[..3].to_vec();
+        members.sort();
+        assert_eq!(members, vec!["a", "b", "c"]);
+        assert!(error.to_string().starts_with("ordering cycle in stage update: "));
+
+        let mut schedule = Schedule::empty();
+        schedule.add_stage("update");
+        schedule.add_system("update", push("x").label("x").after("x"));
+        assert!(schedule.run(&mut world).is_err());
+    }
+
+    #[test]
+    fn stages_run_in_order_and_startup_only_once() {
+        let mut world = world();
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(CoreStage::Update, push("update"))
+            .add_startup_system(push("startup"))
+            .add_stage_before(CoreStage::Update, "early")
+            .add_stage_after(CoreStage::Update, "late")
+            .add_system("late", push("late"))
+            .add_system("early", push("early"))
+            .add_system(CoreStage::PreUpdate, push("pre_update"));
+
+        schedule.run(&mut world).unwrap();
+        schedule.run(&mut world).unwrap();
+        assert_eq!(
+            log(&mut world),
+            vec!["startup", "pre_update", "early", "update", "late", "pre_update", "early", "update", "late"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "stage missing does not exist")]
+    fn adding_to_a_missing_stage_panics() {
+        Schedule::new().add_system("missing", push("a"));
+    }
+}
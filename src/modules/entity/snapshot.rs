@@ -0,0 +1,942 @@
+use std::collections::HashMap;
+use std::convert::{ TryFrom, TryInto };
+use std::error::Error;
+use std::fmt::{ self, Write };
+
+use super::entity_map::Entity;
+use super::reflect::{ Converted, ResourceRegistration, TypeRegistration, TypeRegistry };
+use super::value::{ Value, ValueError };
+use super::world::World;
+
+/// First bytes of every binary snapshot
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"ECSS";
+
+/// Written into both formats, bumped whenever either of them changes. Snapshots from newer versions
+/// are refused instead of misread
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Values nested deeper than this are treated as corrupt rather than risking the stack
+const MAX_DEPTH: usize = 128;
+
+const TAG_UNIT: u8 = 0;
+const TAG_BOOL: u8 = 1;
+const TAG_INT: u8 = 2;
+const TAG_FLOAT: u8 = 3;
+const TAG_STRING: u8 = 4;
+const TAG_ENTITY: u8 = 5;
+const TAG_LIST: u8 = 6;
+const TAG_STRUCT: u8 = 7;
+
+/// Every registered Component and Resource of a World, in a form that can be written out
+///
+/// Entities keep the handles they had in the saved World, components refer to each other through
+/// those and get remapped on load
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorldSnapshot {
+    pub resources: Vec<(String, Value)>,
+    pub entities: Vec<EntitySnapshot>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntitySnapshot {
+    pub entity: Entity,
+    /// Registered name and value of every registered Component the Entity had
+    pub components: Vec<(String, Value)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SnapshotError {
+    /// The bytes do not start with `SNAPSHOT_MAGIC`
+    BadMagic,
+    /// Written by a newer version than this one understands
+    UnsupportedVersion(u32),
+    /// The binary snapshot is truncated or malformed
+    Corrupt(String),
+    /// The text snapshot does not follow the format
+    Parse { line: usize, message: String },
+    /// The snapshot has a Component under a name nothing was registered as
+    UnknownComponent(String),
+    UnknownResource(String),
+    /// A stored Value does not fit the type registered under `name`
+    Value { name: String, error: ValueError },
+    /// A list, string or table has more entries than the binary format can count
+    TooLarge(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::BadMagic => write!(f, "not a world snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "snapshot version {} is newer than {}", version, SNAPSHOT_VERSION)
+            }
+            SnapshotError::Corrupt(message) => write!(f, "corrupt snapshot: {}", message),
+            SnapshotError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            SnapshotError::UnknownComponent(name) => write!(f, "no component registered as {}", name),
+            SnapshotError::UnknownResource(name) => write!(f, "no resource registered as {}", name),
+            SnapshotError::Value { name, error } => write!(f, "{}: {}", name, error),
+            SnapshotError::TooLarge(what) => write!(f, "too many {} for a binary snapshot", what),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+/// Registration and converted Value of every Resource in a snapshot
+type ConvertedResources<'r> = Vec<(&'r ResourceRegistration, Converted)>;
+
+/// Entity in the loading World, registration and converted Value of every Component in a snapshot
+type ConvertedComponents<'r> = Vec<(Entity, &'r TypeRegistration, Converted)>;
+
+impl World {
+    /// Every Component and Resource registered with `register_type` or `register_resource_type`
+    ///
+    /// Every Entity is saved, even without any registered Components, so references to it stay intact
+    pub fn save_snapshot(&self) -> WorldSnapshot {
+        let registry = self.type_registry();
+        let resources = registry
+            .resources()
+            .filter_map(|resource| Some((resource.name().to_string(), resource.get(self)?)))
+            .collect();
+        let entities = self
+            .iter_entities()
+            .map(|entity| EntitySnapshot {
+                entity,
+                components: registry
+                    .iter()
+                    .filter_map(|component| Some((component.name().to_string(), component.get(self, entity)?)))
+                    .collect(),
+            })
+            .collect();
+        WorldSnapshot { resources, entities }
+    }
+
+    /// Spawns every Entity in the snapshot, returning which new Entity each saved one became
+    ///
+    /// Names are looked up in this World's type registry. Entity references inside Components and
+    /// Resources are remapped to match. References to Entities that were not part of the snapshot are
+    /// pointed at `Entity::dangling` handles, so they stay dangling instead of hitting something
+    /// unrelated. Every name is looked up and every Value converted before the first Entity is
+    /// spawned, on failure the World is not touched at all
+    pub fn load_snapshot(&mut self, snapshot: &WorldSnapshot) -> Result<HashMap<Entity, Entity>, SnapshotError> {
+        // Cloned since the registrations are needed while the World is changed
+        let registry = self.type_registry().clone();
+        for (name, _) in &snapshot.resources {
+            if registry.get_resource(name).is_none() {
+                return Err(SnapshotError::UnknownResource(name.clone()));
+            }
+        }
+        for (name, _) in snapshot.entities.iter().flat_map(|entity| &entity.components) {
+            if registry.get(name).is_none() {
+                return Err(SnapshotError::UnknownComponent(name.clone()));
+            }
+        }
+
+        // Which Entities a Value refers to never decides whether it converts, so checking the Values
+        // as saved catches every failure before anything is spawned
+        for (name, value) in &snapshot.resources {
+            registry
+                .get_resource(name)
+                .unwrap()
+                .convert(value)
+                .map_err(|error| SnapshotError::Value { name: name.clone(), error })?;
+        }
+        for (name, value) in snapshot.entities.iter().flat_map(|entity| &entity.components) {
+            registry
+                .get(name)
+                .unwrap()
+                .convert(value)
+                .map_err(|error| SnapshotError::Value { name: name.clone(), error })?;
+        }
+
+        let entities: HashMap<Entity, Entity> = snapshot
+            .entities
+            .iter()
+            .map(|saved| (saved.entity, self.spawn_empty()))
+            .collect();
+        let (resources, components) = convert_snapshot(&registry, snapshot, &entities)
+            .expect("snapshot Values converted before the Entities were spawned");
+
+        for (registration, value) in resources {
+            registration.insert(self, value);
+        }
+        for (entity, registration, value) in components {
+            registration.insert(self, entity, value);
+        }
+        Ok(entities)
+    }
+}
+
+/// Remaps and converts every Value in the snapshot, every Entity outside of it gets its own dangling
+/// handle
+fn convert_snapshot<'r>(
+    registry: &'r TypeRegistry,
+    snapshot: &WorldSnapshot,
+    entities: &HashMap<Entity, Entity>,
+) -> Result<(ConvertedResources<'r>, ConvertedComponents<'r>), SnapshotError> {
+    let mut dangling = HashMap::new();
+    let mut remap = |value: &Value| {
+        let mut value = value.clone();
+        value.map_entities(&mut |entity| match entities.get(&entity) {
+            Some(&entity) => entity,
+            None => {
+                let next = dangling.len();
+                *dangling.entry(entity).or_insert_with(|| Entity::dangling(next))
+            }
+        });
+        value
+    };
+
+    let mut resources = Vec::with_capacity(snapshot.resources.len());
+    for (name, value) in &snapshot.resources {
+        let registration = registry.get_resource(name).unwrap();
+        let value = registration
+            .convert(&remap(value))
+            .map_err(|error| SnapshotError::Value { name: name.clone(), error })?;
+        resources.push((registration, value));
+    }
+    let mut components = Vec::new();
+    for saved in &snapshot.entities {
+        for (name, value) in &saved.components {
+            let registration = registry.get(name).unwrap();
+            let value = registration
+                .convert(&remap(value))
+                .map_err(|error| SnapshotError::Value { name: name.clone(), error })?;
+            components.push((entities[&saved.entity], registration, value));
+        }
+    }
+    Ok((resources, components))
+}
+
+impl WorldSnapshot {
+    /// The compact binary format
+    ///
+    /// After the magic and version comes a table of every name used, Component, Resource and field
+    /// names alike, which the rest refers to by index. Numbers are little endian
+    ///
+    /// Fails if a list, string or the whole snapshot has more entries than fit in a u32
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        let mut encoder = Encoder::default();
+        encoder.len(self.resources.len(), "resources")?;
+        for (name, value) in &self.resources {
+            encoder.name(name)?;
+            encoder.value(value)?;
+        }
+        encoder.len(self.entities.len(), "entities")?;
+        for entity in &self.entities {
+            encoder.u64(entity.entity.to_bits());
+            encoder.len(entity.components.len(), "components")?;
+            for (name, value) in &entity.components {
+                encoder.name(name)?;
+                encoder.value(value)?;
+            }
+        }
+
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&count(encoder.names.len(), "names")?.to_le_bytes());
+        for name in &encoder.names {
+            bytes.extend_from_slice(&count(name.len(), "bytes in a name")?.to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+        }
+        bytes.extend_from_slice(&encoder.body);
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<WorldSnapshot, SnapshotError> {
+        let mut decoder = Decoder {
+            bytes,
+            names: Vec::new(),
+        };
+        if decoder.take(SNAPSHOT_MAGIC.len()).ok() != Some(&SNAPSHOT_MAGIC[..]) {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = decoder.u32()?;
+        if version > SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        for _ in 0..decoder.u32()? {
+            let name = decoder.string()?;
+            decoder.names.push(name);
+        }
+
+        let mut snapshot = WorldSnapshot::default();
+        for _ in 0..decoder.u32()? {
+            snapshot.resources.push((decoder.name()?, decoder.value(0)?));
+        }
+        for _ in 0..decoder.u32()? {
+            let entity = Entity::from_bits(decoder.u64()?);
+            let mut components = Vec::new();
+            for _ in 0..decoder.u32()? {
+                components.push((decoder.name()?, decoder.value(0)?));
+            }
+            snapshot.entities.push(EntitySnapshot { entity, components });
+        }
+        if !decoder.bytes.is_empty() {
+            return Err(corrupt("trailing bytes"));
+        }
+        Ok(snapshot)
+    }
+
+    /// The human readable format, meant to be diffed and edited by hand
+    ///
+    /// ```text
+    /// snapshot 1
+    /// resource Gravity = { x: 0.0, y: -9.8, z: 0.0 }
+    /// entity @0v0 {
+    ///     Transform = { translation: { x: 1.0, y: 0.0, z: 0.0 }, ... }
+    ///     Parent = @3v1
+    /// }
+    /// ```
+    ///
+    /// Entities are written `@index v generation`, `()` is unit, lists use `[]` and structs `{}`.
+    /// Names that are not plain identifiers are quoted, `#` starts a comment
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "snapshot {}", SNAPSHOT_VERSION).unwrap();
+        for (name, value) in &self.resources {
+            out.push_str("resource ");
+            write_name(&mut out, name);
+            out.push_str(" = ");
+            write_value(&mut out, value);
+            out.push('\n');
+        }
+        for entity in &self.entities {
+            write!(out, "entity @{} {{", entity.entity).unwrap();
+            if !entity.components.is_empty() {
+                out.push('\n');
+            }
+            for (name, value) in &entity.components {
+                out.push_str("    ");
+                write_name(&mut out, name);
+                out.push_str(" = ");
+                write_value(&mut out, value);
+                out.push('\n');
+            }
+            out.push_str("}\n");
+        }
+        out
+    }
+
+    pub fn from_text(text: &str) -> Result<WorldSnapshot, SnapshotError> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+            last_line: text.lines().count().max(1),
+        };
+        parser.keyword("snapshot")?;
+        let version = match parser.next()? {
+            Token::Number(number) => number.parse::<u32>().map_err(|_| parser.error("expected a version"))?,
+            _ => return Err(parser.error("expected a version")),
+        };
+        if version > SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let mut snapshot = WorldSnapshot::default();
+        while parser.peek().is_some() {
+            match parser.next()? {
+                Token::Ident(keyword) if keyword == "resource" => {
+                    let name = parser.name()?;
+                    parser.punct('=')?;
+                    snapshot.resources.push((name, parser.value(0)?));
+                }
+                Token::Ident(keyword) if keyword == "entity" => {
+                    let entity = match parser.next()? {
+                        Token::Entity(entity) => entity,
+                        _ => return Err(parser.error("expected an entity")),
+                    };
+                    parser.punct('{')?;
+                    let mut components = Vec::new();
+                    while !parser.eat('}') {
+                        let name = parser.name()?;
+                        parser.punct('=')?;
+                        components.push((name, parser.value(0)?));
+                    }
+                    snapshot.entities.push(EntitySnapshot { entity, components });
+                }
+                _ => return Err(parser.error("expected resource or entity")),
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+fn corrupt(message: &str) -> SnapshotError {
+    SnapshotError::Corrupt(message.to_string())
+}
+
+/// Lengths and indices are stored as u32
+fn count(len: usize, what: &str) -> Result<u32, SnapshotError> {
+    u32::try_from(len).map_err(|_| SnapshotError::TooLarge(what.to_string()))
+}
+
+#[derive(Default)]
+struct Encoder {
+    body: Vec<u8>,
+    names: Vec<String>,
+    indices: HashMap<String, u32>,
+}
+
+impl Encoder {
+    fn u32(&mut self, value: u32) {
+        self.body.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.body.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize, what: &str) -> Result<(), SnapshotError> {
+        let len = count(len, what)?;
+        self.u32(len);
+        Ok(())
+    }
+
+    fn name(&mut self, name: &str) -> Result<(), SnapshotError> {
+        let index = match self.indices.get(name) {
+            Some(&index) => index,
+            None => {
+                let index = count(self.names.len(), "names")?;
+                self.names.push(name.to_string());
+                self.indices.insert(name.to_string(), index);
+                index
+            }
+        };
+        self.u32(index);
+        Ok(())
+    }
+
+    fn value(&mut self, value: &Value) -> Result<(), SnapshotError> {
+        match value {
+            Value::Unit => self.body.push(TAG_UNIT),
+            Value::Bool(value) => {
+                self.body.push(TAG_BOOL);
+                self.body.push(*value as u8);
+            }
+            Value::Int(value) => {
+                self.body.push(TAG_INT);
+                self.u64(*value as u64);
+            }
+            Value::Float(value) => {
+                self.body.push(TAG_FLOAT);
+                self.u64(value.to_bits());
+            }
+            Value::String(value) => {
+                self.body.push(TAG_STRING);
+                self.len(value.len(), "bytes in a string")?;
+                self.body.extend_from_slice(value.as_bytes());
+            }
+            Value::Entity(entity) => {
+                self.body.push(TAG_ENTITY);
+                self.u64(entity.to_bits());
+            }
+            Value::List(items) => {
+                self.body.push(TAG_LIST);
+                self.len(items.len(), "list items")?;
+                for item in items {
+                    self.value(item)?;
+                }
+            }
+            Value::Struct(fields) => {
+                self.body.push(TAG_STRUCT);
+                self.len(fields.len(), "struct fields")?;
+                for (name, value) in fields {
+                    self.name(name)?;
+                    self.value(value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    names: Vec<String>,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() < len {
+            return Err(corrupt("unexpected end"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, SnapshotError> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| corrupt("invalid utf-8"))
+    }
+
+    fn name(&mut self) -> Result<String, SnapshotError> {
+        let index = self.u32()? as usize;
+        self.names.get(index).cloned().ok_or_else(|| corrupt("name index out of range"))
+    }
+
+    /// Lengths are not trusted for preallocation, every element takes at least a byte
+    fn len(&mut self) -> Result<usize, SnapshotError> {
+        let len = self.u32()? as usize;
+        if len > self.bytes.len() {
+            return Err(corrupt("unexpected end"));
+        }
+        Ok(len)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, SnapshotError> {
+        if depth > MAX_DEPTH {
+            return Err(corrupt("values nested too deep"));
+        }
+        Ok(match self.u8()? {
+            TAG_UNIT => Value::Unit,
+            TAG_BOOL => match self.u8()? {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                _ => return Err(corrupt("invalid bool")),
+            },
+            TAG_INT => Value::Int(self.u64()? as i64),
+            TAG_FLOAT => Value::Float(f64::from_bits(self.u64()?)),
+            TAG_STRING => Value::String(self.string()?),
+            TAG_ENTITY => Value::Entity(Entity::from_bits(self.u64()?)),
+            TAG_LIST => {
+                let len = self.len()?;
+                let mut items = Vec::with_capacity(len);
+                for _ in 0..len {
+                    items.push(self.value(depth + 1)?);
+                }
+                Value::List(items)
+            }
+            TAG_STRUCT => {
+                let len = self.len()?;
+                let mut fields = Vec::with_capacity(len);
+                for _ in 0..len {
+                    fields.push((self.name()?, self.value(depth + 1)?));
+                }
+                Value::Struct(fields)
+            }
+            tag => return Err(SnapshotError::Corrupt(format!("invalid value tag {}", tag))),
+        })
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn write_name(out: &mut String, name: &str) {
+    if is_identifier(name) {
+        out.push_str(name);
+    } else {
+        write!(out, "{:?}", name).unwrap();
+    }
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Unit => out.push_str("()"),
+        Value::Bool(value) => write!(out, "{}", value).unwrap(),
+        Value::Int(value) => write!(out, "{}", value).unwrap(),
+        // Debug always keeps a `.0` or exponent, which is what tells floats and ints apart
+        Value::Float(value) => write!(out, "{:?}", value).unwrap(),
+        Value::String(value) => write!(out, "{:?}", value).unwrap(),
+        Value::Entity(entity) => write!(out, "@{}", entity).unwrap(),
+        Value::List(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_value(out, item);
+            }
+            out.push(']');
+        }
+        Value::Struct(fields) if fields.is_empty() => out.push_str("{}"),
+        Value::Struct(fields) => {
+            out.push_str("{ ");
+            for (i, (name, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_name(out, name);
+                out.push_str(": ");
+                write_value(out, value);
+            }
+            out.push_str(" }");
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    /// Left unparsed until it is known whether an int or a float is wanted
+    Number(String),
+    Entity(Entity),
+    Punct(char),
+}
+
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, SnapshotError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    let error = |line: usize, message: &str| SnapshotError::Parse {
+        line,
+        message: message.to_string(),
+    };
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\n' => {
+                line += 1;
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '#' => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            '{' | '}' | '[' | ']' | '(' | ')' | ',' | ':' | '=' => {
+                chars.next();
+                tokens.push((Token::Punct(c), line));
+            }
+            '"' => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => string.push(match chars.next() {
+                            Some('n') => '\n',
+                            Some('r') => '\r',
+                            Some('t') => '\t',
+                            Some('0') => '\0',
+                            Some(c @ '\\') | Some(c @ '"') | Some(c @ '\'') => c,
+                            Some('u') => {
+                                if chars.next() != Some('{') {
+                                    return Err(error(line, "expected { after \\u"));
+                                }
+                                let mut hex = String::new();
+                                for c in chars.by_ref() {
+                                    if c == '}' {
+                                        break;
+                                    }
+                                    hex.push(c);
+                                }
+                                u32::from_str_radix(&hex, 16)
+                                    .ok()
+                                    .and_then(std::char::from_u32)
+                                    .ok_or_else(|| error(line, "invalid unicode escape"))?
+                            }
+                            _ => return Err(error(line, "invalid escape")),
+                        }),
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            string.push(c);
+                        }
+                        None => return Err(error(line, "unterminated string")),
+                    }
+                }
+                tokens.push((Token::Str(string), line));
+            }
+            '@' => {
+                chars.next();
+                let mut word = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric()) {
+                    word.push(c);
+                    chars.next();
+                }
+                let entity = word
+                    .split_once('v')
+                    .and_then(|(index, generation)| Some(Entity::new(index.parse().ok()?, generation.parse().ok()?)))
+                    .ok_or_else(|| error(line, "expected an entity like @3v0"))?;
+                tokens.push((Token::Entity(entity), line));
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '+' => {
+                let mut number = String::new();
+                number.push(c);
+                chars.next();
+                while let Some(&c) = chars.peek() {
+                    let exponent_sign = (c == '-' || c == '+') && number.ends_with(['e', 'E']);
+                    if !(c.is_ascii_alphanumeric() || c == '.' || exponent_sign) {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                tokens.push((Token::Number(number), line));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek().filter(|&&c| c.is_ascii_alphanumeric() || c == '_') {
+                    ident.push(c);
+                    chars.next();
+                }
+                tokens.push((Token::Ident(ident), line));
+            }
+            c => return Err(SnapshotError::Parse { line, message: format!("unexpected {:?}", c) }),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    /// Reported for errors at the end of the input
+    last_line: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> SnapshotError {
+        let line = self
+            .tokens
+            .get(self.position.saturating_sub(1))
+            .map_or(self.last_line, |(_, line)| *line);
+        SnapshotError::Parse {
+            line,
+            message: message.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Result<Token, SnapshotError> {
+        match self.tokens.get(self.position) {
+            Some((token, _)) => {
+                self.position += 1;
+                Ok(token.clone())
+            }
+            None => Err(SnapshotError::Parse {
+                line: self.last_line,
+                message: "unexpected end".to_string(),
+            }),
+        }
+    }
+
+    /// Consumes the punctuation if it is next
+    fn eat(&mut self, punct: char) -> bool {
+        if self.peek() == Some(&Token::Punct(punct)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn punct(&mut self, punct: char) -> Result<(), SnapshotError> {
+        match self.next()? {
+            Token::Punct(c) if c == punct => Ok(()),
+            _ => Err(self.error(&format!("expected {}", punct))),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), SnapshotError> {
+        match self.next()? {
+            Token::Ident(ident) if ident == keyword => Ok(()),
+            _ => Err(self.error(&format!("expected {}", keyword))),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, SnapshotError> {
+        match self.next()? {
+            Token::Ident(name) | Token::Str(name) => Ok(name),
+            _ => Err(self.error("expected a name")),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, SnapshotError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("values nested too deep"));
+        }
+        Ok(match self.next()? {
+            Token::Punct('(') => {
+                self.punct(')')?;
+                Value::Unit
+            }
+            Token::Ident(ident) => match ident.as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                "inf" | "NaN" => Value::Float(ident.parse().unwrap()),
+                _ => return Err(self.error(&format!("unexpected {}", ident))),
+            },
+            Token::Number(number) => {
+                if number.contains(['.', 'e', 'E', 'i', 'N']) {
+                    Value::Float(number.parse().map_err(|_| self.error("invalid float"))?)
+                } else {
+                    Value::Int(number.parse().map_err(|_| self.error("invalid int"))?)
+                }
+            }
+            Token::Str(string) => Value::String(string),
+            Token::Entity(entity) => Value::Entity(entity),
+            Token::Punct('[') => {
+                let mut items = Vec::new();
+                while !self.eat(']') {
+                    items.push(self.value(depth + 1)?);
+                    if !self.eat(',') {
+                        self.punct(']')?;
+                        break;
+                    }
+                }
+                Value::List(items)
+            }
+            Token::Punct('{') => {
+                let mut fields = Vec::new();
+                while !self.eat('}') {
+                    let name = self.name()?;
+                    self.punct(':')?;
+                    fields.push((name, self.value(depth + 1)?));
+                    if !self.eat(',') {
+                        self.punct('}')?;
+                        break;
+                    }
+                }
+                Value::Struct(fields)
+            }
+            _ => return Err(self.error("expected a value")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::entity::hierarchy::{ Children, Parent };
+
+    #[derive(Debug, PartialEq)]
+    struct Target {
+        name: String,
+        entity: Entity,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Focus {
+        entity: Option<Entity>,
+    }
+
+    crate::reflect_struct!(Target { name: String, entity: Entity });
+    crate::reflect_struct!(Focus { entity: Option<Entity> });
+
+    fn world() -> World {
+        let mut world = World::new();
+        assert!(world.register_type::<Target>("Target"));
+        assert!(world.register_type::<Parent>("Parent"));
+        assert!(world.register_type::<Children>("Children"));
+        assert!(world.register_resource_type::<Focus>("Focus"));
+        assert!(!world.register_resource_type::<Focus>("Other"));
+        world
+    }
+
+    fn target(name: &str, entity: Entity) -> Target {
+        Target {
+            name: name.to_string(),
+            entity,
+        }
+    }
+
+    /// A root with a child, where the child points at an Entity that is not saved
+    fn saved() -> (WorldSnapshot, [Entity; 2]) {
+        let mut world = world();
+        let gone = world.spawn(());
+        let root = world.spawn(());
+        let child = world.spawn((target("child", gone),));
+        world.insert(root, target("root", child));
+        world.attach(child, root).unwrap();
+        world.insert_resource(Focus { entity: Some(child) });
+        world.despawn(gone);
+        (world.save_snapshot(), [root, child])
+    }
+
+    #[test]
+    fn snapshots_round_trip_through_both_formats() {
+        let (snapshot, _) = saved();
+        assert_eq!(snapshot.entities.len(), 2);
+
+        let bytes = snapshot.to_bytes().unwrap();
+        assert_eq!(&bytes[..4], &SNAPSHOT_MAGIC);
+        assert_eq!(WorldSnapshot::from_bytes(&bytes), Ok(snapshot.clone()));
+        assert_eq!(WorldSnapshot::from_text(&snapshot.to_text()), Ok(snapshot.clone()));
+        assert_eq!(WorldSnapshot::from_bytes(&bytes[..bytes.len() - 1]), Err(corrupt("unexpected end")));
+        assert_eq!(WorldSnapshot::from_bytes(b"nope"), Err(SnapshotError::BadMagic));
+    }
+
+    #[test]
+    fn loading_remaps_entity_references() {
+        let (snapshot, [root, child]) = saved();
+        let mut world = world();
+        let existing = world.spawn((target("existing", root),));
+        let entities = world.load_snapshot(&snapshot).unwrap();
+        let (new_root, new_child) = (entities[&root], entities[&child]);
+
+        assert_eq!(world.len(), 3);
+        assert_eq!(world.get::<Target>(existing), Some(&target("existing", root)));
+        assert_eq!(world.get::<Target>(new_root), Some(&target("root", new_child)));
+        assert_eq!(world.children(new_root), &[new_child]);
+        assert_eq!(world.parent(new_child), Ok(Some(new_root)));
+        assert_eq!(world.resource::<Focus>(), Some(&Focus { entity: Some(new_child) }));
+
+        let dangling = world.get::<Target>(new_child).unwrap().entity;
+        assert_eq!(dangling, Entity::dangling(0));
+        assert!(!world.contains(dangling));
+        assert_eq!(world.removed_components().despawned().event_count(), 0);
+        assert!(!entities.values().any(|&entity| entity == dangling));
+    }
+
+    #[test]
+    fn failed_loads_leave_the_world_as_it_was() {
+        let (mut snapshot, _) = saved();
+        snapshot.entities[1].components[0].1 = Value::Int(3);
+        let mut world = world();
+        world.spawn(());
+        let despawned = world.removed_components().despawned().event_count();
+
+        let error = world.load_snapshot(&snapshot).unwrap_err();
+        assert!(matches!(error, SnapshotError::Value { ref name, .. } if name == "Target"));
+        assert_eq!(world.len(), 1);
+        assert_eq!(world.removed_components().despawned().event_count(), despawned);
+        assert!(world.resource::<Focus>().is_none());
+
+        snapshot.entities[0].components.push(("Missing".to_string(), Value::Unit));
+        assert_eq!(
+            world.load_snapshot(&snapshot),
+            Err(SnapshotError::UnknownComponent("Missing".to_string()))
+        );
+        assert_eq!(world.len(), 1);
+        assert_eq!(world.removed_components().despawned().event_count(), despawned);
+        assert_eq!(world.spawn(()), Entity::new(1, 0));
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn lengths_past_u32_are_refused() {
+        assert_eq!(count(u32::MAX as usize, "items"), Ok(u32::MAX));
+        assert_eq!(count(u32::MAX as usize + 1, "items"), Err(SnapshotError::TooLarge("items".to_string())));
+    }
+}
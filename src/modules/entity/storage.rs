@@ -0,0 +1,425 @@
+use std::any::Any;
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+
+use super::change_detection::{ ComponentTicks, Tick };
+use super::entity_map::Entity;
+
+/// How a single Component type is laid out in memory, picked when the Component is registered
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum StorageType {
+    /// A column in the Entity's archetype table, best for Components that are iterated together
+    #[default]
+    Table,
+    /// One slot per Entity index, best for Components nearly every Entity has
+    DenseVec,
+    /// Packed values with an index lookup, best for rare Components such as tags
+    SparseSet,
+    /// Hashed by Entity, best when Entity indices are huge and very spread out
+    HashMap,
+}
+
+impl StorageType {
+    /// Creates the backend for every StorageType except Table, which lives in archetype columns
+    pub fn new_storage<T: Send + Sync + 'static>(self) -> BoxedStorage<T> {
+        match self {
+            StorageType::Table => panic!("table components are stored in archetype columns"),
+            StorageType::DenseVec => Box::new(DenseVecStorage::<T>::default()),
+            StorageType::SparseSet => Box::new(SparseSetStorage::<T>::default()),
+            StorageType::HashMap => Box::new(HashMapStorage::<T>::default()),
+        }
+    }
+}
+
+/// Storage backend for a single Component type, every backend is addressed by Entity
+/// 
+/// Lookups with a stale Entity (same index, older generation) must miss
+pub trait ComponentStorage<T>: Send + Sync {
+    /// Stores the value for the Entity, returning the value it replaced
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T>;
+    fn remove(&mut self, entity: Entity) -> Option<T>;
+    fn get(&self, entity: Entity) -> Option<&T>;
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T>;
+    fn len(&self) -> usize;
+    /// Visits every stored value, in no particular order
+    fn for_each_mut(&mut self, f: &mut dyn FnMut(Entity, &mut T));
+
+    fn contains(&self, entity: Entity) -> bool {
+        self.get(entity).is_some()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Lets Queries hand out `&mut` to Component values while only holding `&World`
+///
+/// Nothing here stops two mutable borrows of the same value, that is what the Query access checks
+/// are for, so every read or write through `get` must be covered by one of them
+#[repr(transparent)]
+pub struct SyncCell<T>(UnsafeCell<T>);
+
+unsafe impl<T: Send + Sync> Sync for SyncCell<T> {}
+
+impl<T> SyncCell<T> {
+    pub fn new(value: T) -> SyncCell<T> {
+        SyncCell(UnsafeCell::new(value))
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0.into_inner()
+    }
+
+    pub fn get(&self) -> *mut T {
+        self.0.get()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.0.get_mut()
+    }
+
+    /// # Safety
+    /// Nobody may be writing to the value for as long as the returned reference lives
+    pub unsafe fn deref(&self) -> &T {
+        &*self.0.get()
+    }
+}
+
+/// A Component value in one of the non Table storages, alongside its change ticks
+pub struct ComponentCell<T> {
+    value: SyncCell<T>,
+    ticks: SyncCell<ComponentTicks>,
+}
+
+impl<T> ComponentCell<T> {
+    pub fn new(value: T, tick: Tick) -> ComponentCell<T> {
+        ComponentCell {
+            value: SyncCell::new(value),
+            ticks: SyncCell::new(ComponentTicks::new(tick)),
+        }
+    }
+
+    pub fn value(&self) -> &SyncCell<T> {
+        &self.value
+    }
+
+    pub fn ticks(&self) -> &SyncCell<ComponentTicks> {
+        &self.ticks
+    }
+
+    pub fn parts_mut(&mut self) -> (&mut T, &mut ComponentTicks) {
+        (self.value.get_mut(), self.ticks.get_mut())
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+pub type BoxedStorage<T> = Box<dyn ComponentStorage<T>>;
+
+/// Type erased view of a single Component's storage so despawning can reach every type
+pub trait AnyStorage: Send + Sync {
+    fn remove_entity(&mut self, entity: Entity);
+    /// Clamps every change tick that is about to become too old to compare against `tick`
+    fn check_change_ticks(&mut self, tick: Tick);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Send + Sync + 'static> AnyStorage for BoxedStorage<ComponentCell<T>> {
+    fn remove_entity(&mut self, entity: Entity) {
+        self.remove(entity);
+    }
+
+    fn check_change_ticks(&mut self, tick: Tick) {
+        self.for_each_mut(&mut |_, cell| cell.ticks.get_mut().check_ticks(tick));
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Values live directly at the Entity's index
+pub struct DenseVecStorage<T> {
+    items: Vec<Option<(Entity, T)>>,
+    len: usize,
+}
+
+impl<T> Default for DenseVecStorage<T> {
+    fn default() -> DenseVecStorage<T> {
+        DenseVecStorage {
+            items: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<T: Send + Sync> ComponentStorage<T> for DenseVecStorage<T> {
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        let index = entity.index();
+        if index >= self.items.len() {
+            self.items.resize_with(index + 1, || None);
+        }
+
+        match self.items[index].replace((entity, value)) {
+            Some((old, value)) if old == entity => Some(value),
+            Some(_) => None,
+            None => {
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.get(entity)?;
+        self.len -= 1;
+        self.items[entity.index()].take().map(|(_, value)| value)
+    }
+
+    fn get(&self, entity: Entity) -> Option<&T> {
+        match self.items.get(entity.index()) {
+            Some(Some((e, value))) if *e == entity => Some(value),
+            _ => None,
+        }
+    }
+
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.items.get_mut(entity.index()) {
+            Some(Some((e, value))) if *e == entity => Some(value),
+            _ => None,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn for_each_mut(&mut self, f: &mut dyn FnMut(Entity, &mut T)) {
+        for (entity, value) in self.items.iter_mut().flatten() {
+            f(*entity, value);
+        }
+    }
+}
+
+/// Values are packed together, `sparse` maps an Entity index to its position in `dense`
+pub struct SparseSetStorage<T> {
+    sparse: Vec<Option<usize>>,
+    dense: Vec<Entity>,
+    values: Vec<T>,
+}
+
+impl<T> Default for SparseSetStorage<T> {
+    fn default() -> SparseSetStorage<T> {
+        SparseSetStorage {
+            sparse: Vec::new(),
+            dense: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<T> SparseSetStorage<T> {
+    fn dense_index(&self, entity: Entity) -> Option<usize> {
+        let dense_index = (*self.sparse.get(entity.index())?)?;
+        if self.dense[dense_index] == entity {
+            Some(dense_index)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Send + Sync> ComponentStorage<T> for SparseSetStorage<T> {
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        let index = entity.index();
+        if index >= self.sparse.len() {
+            self.sparse.resize(index + 1, None);
+        }
+
+        match self.sparse[index] {
+            Some(dense_index) if self.dense[dense_index] == entity => {
+                Some(std::mem::replace(&mut self.values[dense_index], value))
+            }
+            Some(dense_index) => {
+                // A stale Entity still owns the slot, take it over
+                self.dense[dense_index] = entity;
+                self.values[dense_index] = value;
+                None
+            }
+            None => {
+                self.sparse[index] = Some(self.dense.len());
+                self.dense.push(entity);
+                self.values.push(value);
+                None
+            }
+        }
+    }
+
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        let dense_index = self.dense_index(entity)?;
+        self.sparse[entity.index()] = None;
+        self.dense.swap_remove(dense_index);
+        let value = self.values.swap_remove(dense_index);
+
+        if let Some(moved) = self.dense.get(dense_index) {
+            self.sparse[moved.index()] = Some(dense_index);
+        }
+        Some(value)
+    }
+
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.dense_index(entity).map(|i| &self.values[i])
+    }
+
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.dense_index(entity).map(move |i| &mut self.values[i])
+    }
+
+    fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    fn for_each_mut(&mut self, f: &mut dyn FnMut(Entity, &mut T)) {
+        for (entity, value) in self.dense.iter().zip(self.values.iter_mut()) {
+            f(*entity, value);
+        }
+    }
+}
+
+/// Values are hashed by their Entity
+pub struct HashMapStorage<T> {
+    items: HashMap<Entity, T>,
+}
+
+impl<T> Default for HashMapStorage<T> {
+    fn default() -> HashMapStorage<T> {
+        HashMapStorage {
+            items: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Send + Sync> ComponentStorage<T> for HashMapStorage<T> {
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        self.items.insert(entity, value)
+    }
+
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.items.remove(&entity)
+    }
+
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.items.get(&entity)
+    }
+
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.items.get_mut(&entity)
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn for_each_mut(&mut self, f: &mut dyn FnMut(Entity, &mut T)) {
+        for (entity, value) in self.items.iter_mut() {
+            f(*entity, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::entity::world::World;
+
+    const BACKENDS: [StorageType; 3] = [StorageType::DenseVec, StorageType::SparseSet, StorageType::HashMap];
+
+    #[test]
+    fn every_backend_stores_and_removes() {
+        for &storage_type in &BACKENDS {
+            let mut storage = storage_type.new_storage::<u32>();
+            let a = Entity::new(0, 0);
+            let b = Entity::new(7, 0);
+            assert_eq!(storage.insert(a, 1), None);
+            assert_eq!(storage.insert(b, 2), None);
+            assert_eq!(storage.insert(a, 3), Some(1), "{:?}", storage_type);
+            assert_eq!(storage.len(), 2);
+
+            *storage.get_mut(b).unwrap() += 10;
+            assert_eq!(storage.get(b), Some(&12));
+            assert_eq!(storage.remove(a), Some(3));
+            assert_eq!(storage.remove(a), None);
+            assert_eq!(storage.len(), 1);
+
+            let mut seen = Vec::new();
+            storage.for_each_mut(&mut |entity, value| seen.push((entity, *value)));
+            assert_eq!(seen, vec![(b, 12)]);
+        }
+    }
+
+    #[test]
+    fn every_backend_rejects_stale_entities() {
+        for &storage_type in &BACKENDS {
+            let mut storage = storage_type.new_storage::<u32>();
+            let old = Entity::new(3, 0);
+            let new = Entity::new(3, 1);
+            storage.insert(old, 1);
+
+            assert_eq!(storage.get(new), None, "{:?}", storage_type);
+            assert_eq!(storage.get_mut(new), None);
+            assert_eq!(storage.remove(new), None);
+            assert!(!storage.contains(new));
+            assert_eq!(storage.len(), 1);
+
+            // Once the slot changes hands only the new Entity is found
+            storage.remove(old);
+            storage.insert(new, 2);
+            assert_eq!(storage.get(old), None, "{:?}", storage_type);
+            assert_eq!(storage.get(new), Some(&2));
+        }
+    }
+
+    #[test]
+    fn sparse_set_fixes_up_the_moved_value() {
+        let mut storage = SparseSetStorage::default();
+        let entities: Vec<_> = (0..3).map(|i| Entity::new(i, 0)).collect();
+        for (i, &entity) in entities.iter().enumerate() {
+            storage.insert(entity, i);
+        }
+        storage.remove(entities[0]);
+        assert_eq!(storage.get(entities[1]), Some(&1));
+        assert_eq!(storage.get(entities[2]), Some(&2));
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    #[test]
+    fn world_uses_the_registered_backend() {
+        for &storage_type in &BACKENDS {
+            let mut world = World::new();
+            assert!(world.register_component::<Health>(storage_type));
+            assert!(!world.register_component::<Health>(StorageType::Table));
+
+            let old = world.spawn_empty();
+            world.insert(old, Health(1));
+            let id = world.components().id::<Health>().unwrap();
+            assert_eq!(world.components().info(id).storage_type(), storage_type);
+            assert_eq!(world.storage::<Health>(id).map(|storage| storage.len()), Some(1));
+
+            world.despawn(old);
+            let new = world.spawn_empty();
+            assert_eq!(new.index(), old.index());
+            assert_eq!(world.get::<Health>(new), None, "{:?}", storage_type);
+            assert_eq!(world.storage::<Health>(id).map(|storage| storage.len()), Some(0));
+        }
+    }
+}
@@ -0,0 +1,394 @@
+use std::any::type_name;
+use std::borrow::Cow;
+use std::marker::PhantomData;
+
+use super::access::Access;
+use super::change_detection::{ Tick, MAX_CHANGE_AGE };
+use super::entity_map::Entity;
+use super::query::{ QueryFilter, QueryIter, QueryState, ReadOnlyWorldQuery, WorldQuery };
+use super::world::World;
+
+/// Anything that can be run against the World once per frame
+///
+/// Every System declares up front which Components and resources it reads and writes, schedulers use
+/// that to decide what may run side by side
+pub trait System: Send + Sync + 'static {
+    fn name(&self) -> Cow<'static, str>;
+
+    /// Only meaningful after `initialize`
+    fn access(&self) -> &Access;
+
+    /// Sets up the System's cached state, must be called before it is run
+    fn initialize(&mut self, world: &mut World);
+
+    /// Runs the System with shared access to the World
+    ///
+    /// # Safety
+    /// Nothing may access the World in a way that conflicts with `access` while this runs
+    unsafe fn run_unsafe(&mut self, world: &World);
+
+    /// Applies anything the System held back until it had exclusive access to the World
+    fn apply_deferred(&mut self, _world: &mut World) {}
+
+    /// Clamps the tick of the System's last run so it never grows too old to compare against `tick`
+    fn check_change_tick(&mut self, _tick: Tick) {}
+
+    fn run(&mut self, world: &mut World) {
+        // `&mut World` rules out anyone else touching the World
+        unsafe { self.run_unsafe(world) };
+        self.apply_deferred(world);
+    }
+}
+
+/// What a System knows about itself, shared with its parameters while they are set up and fetched
+///
+/// `last_run` is the change tick of the System's previous run and `this_run` that of the current one,
+/// change detection reports everything that happened in between
+pub struct SystemMeta {
+    name: Cow<'static, str>,
+    access: Access,
+    last_run: Tick,
+    this_run: Tick,
+}
+
+impl SystemMeta {
+    pub fn new<T>() -> SystemMeta {
+        SystemMeta {
+            name: Cow::Borrowed(type_name::<T>()),
+            access: Access::new(),
+            last_run: Tick::default(),
+            this_run: Tick::default(),
+        }
+    }
+
+    pub fn last_run(&self) -> Tick {
+        self.last_run
+    }
+
+    pub fn this_run(&self) -> Tick {
+        self.this_run
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    pub fn access_mut(&mut self) -> &mut Access {
+        &mut self.access
+    }
+}
+
+/// Something a function System can take as a parameter, fetched from the World every run
+///
+/// # Safety
+/// `init_state` has to add everything `get_param` reads or writes to the SystemMeta's access
+pub unsafe trait SystemParam: Sized {
+    type State: Send + Sync + 'static;
+    type Item<'w, 's>: SystemParam<State = Self::State>;
+
+    fn init_state(world: &mut World, meta: &mut SystemMeta) -> Self::State;
+
+    /// # Safety
+    /// The caller has to hold the access registered in `init_state` for as long as the item lives
+    unsafe fn get_param<'w, 's>(state: &'s mut Self::State, meta: &SystemMeta, world: &'w World) -> Self::Item<'w, 's>;
+
+    /// Called with exclusive access to the World after the System has run
+    fn apply(_state: &mut Self::State, _world: &mut World) {}
+}
+
+pub type SystemParamItem<'w, 's, P> = <P as SystemParam>::Item<'w, 's>;
+
+/// System parameter iterating every Entity that matches Q and passes F
+pub struct Query<'w, 's, Q: WorldQuery, F: QueryFilter = ()> {
+    world: &'w World,
+    state: &'s QueryState<Q, F>,
+    last_run: Tick,
+    this_run: Tick,
+}
+
+impl<'w, 's, Q: WorldQuery, F: QueryFilter> Query<'w, 's, Q, F> {
+    pub fn iter(&self) -> QueryIter<'_, 's, Q, F>
+    where
+        Q: ReadOnlyWorldQuery,
+    {
+        // Read only items can be shared freely
+        unsafe { self.state.iter_unchecked(self.world, self.last_run, self.this_run) }
+    }
+
+    pub fn iter_mut(&mut self) -> QueryIter<'_, 's, Q, F> {
+        // `&mut self` keeps any other item from this Query alive at the same time
+        unsafe { self.state.iter_unchecked(self.world, self.last_run, self.this_run) }
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<Q::Item<'_>>
+    where
+        Q: ReadOnlyWorldQuery,
+    {
+        unsafe { self.state.get_unchecked(self.world, entity, self.last_run, self.this_run) }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<Q::Item<'_>> {
+        unsafe { self.state.get_unchecked(self.world, entity, self.last_run, self.this_run) }
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        // Only the filter's reads happen and the Query holds those, `&self` rules out a mutable item
+        // of this Query being alive
+        unsafe { self.state.contains_unchecked(self.world, entity, self.last_run, self.this_run) }
+    }
+}
+
+unsafe impl<'w, 's, Q: WorldQuery + 'static, F: QueryFilter + 'static> SystemParam for Query<'w, 's, Q, F> {
+    type State = QueryState<Q, F>;
+    type Item<'world, 'state> = Query<'world, 'state, Q, F>;
+
+    fn init_state(world: &mut World, meta: &mut SystemMeta) -> QueryState<Q, F> {
+        let state = QueryState::new(world);
+        meta.access.extend(state.access(), world.components());
+        state
+    }
+
+    unsafe fn get_param<'world, 'state>(
+        state: &'state mut QueryState<Q, F>,
+        meta: &SystemMeta,
+        world: &'world World,
+    ) -> Self::Item<'world, 'state> {
+        state.update_archetypes(world);
+        Query {
+            world,
+            state,
+            last_run: meta.last_run,
+            this_run: meta.this_run,
+        }
+    }
+}
+
+macro_rules! impl_tuple_system_param {
+    ($($name: ident),*) => {
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        unsafe impl<$($name: SystemParam),*> SystemParam for ($($name,)*) {
+            type State = ($($name::State,)*);
+            type Item<'w, 's> = ($($name::Item<'w, 's>,)*);
+
+            fn init_state(world: &mut World, meta: &mut SystemMeta) -> Self::State {
+                ($($name::init_state(world, meta),)*)
+            }
+
+            unsafe fn get_param<'w, 's>(state: &'s mut Self::State, meta: &SystemMeta, world: &'w World) -> Self::Item<'w, 's> {
+                let ($($name,)*) = state;
+                ($($name::get_param($name, meta, world),)*)
+            }
+
+            fn apply(state: &mut Self::State, world: &mut World) {
+                let ($($name,)*) = state;
+                $($name::apply($name, world);)*
+            }
+        }
+    };
+}
+
+impl_tuple_system_param!();
+impl_tuple_system_param!(A);
+impl_tuple_system_param!(A, B);
+impl_tuple_system_param!(A, B, C);
+impl_tuple_system_param!(A, B, C, D);
+impl_tuple_system_param!(A, B, C, D, E);
+impl_tuple_system_param!(A, B, C, D, E, F);
+impl_tuple_system_param!(A, B, C, D, E, F, G);
+impl_tuple_system_param!(A, B, C, D, E, F, G, H);
+
+/// Implemented for plain functions whose every argument is a SystemParam
+///
+/// `Marker` only exists so functions of different arities get different impls
+pub trait SystemParamFunction<Marker>: Send + Sync + 'static {
+    type Param: SystemParam;
+
+    fn run(&mut self, param: SystemParamItem<Self::Param>);
+}
+
+macro_rules! impl_system_param_function {
+    ($($param: ident),*) => {
+        #[allow(non_snake_case, clippy::too_many_arguments)]
+        impl<Func, $($param: SystemParam),*> SystemParamFunction<fn($($param,)*)> for Func
+        where
+            Func: Send + Sync + 'static,
+            for<'a> &'a mut Func: FnMut($($param),*) + FnMut($(SystemParamItem<$param>),*),
+        {
+            type Param = ($($param,)*);
+
+            fn run(&mut self, param: SystemParamItem<($($param,)*)>) {
+                // Pins down which FnMut impl to call, the compiler cannot pick it by itself
+                fn call_inner<$($param),*>(mut f: impl FnMut($($param),*), $($param: $param),*) {
+                    f($($param),*)
+                }
+                let ($($param,)*) = param;
+                call_inner(self, $($param),*)
+            }
+        }
+    };
+}
+
+impl_system_param_function!();
+impl_system_param_function!(A);
+impl_system_param_function!(A, B);
+impl_system_param_function!(A, B, C);
+impl_system_param_function!(A, B, C, D);
+impl_system_param_function!(A, B, C, D, E);
+impl_system_param_function!(A, B, C, D, E, F);
+impl_system_param_function!(A, B, C, D, E, F, G);
+impl_system_param_function!(A, B, C, D, E, F, G, H);
+
+/// A plain function turned into a System, its parameters are fetched from the World on every run
+pub struct FunctionSystem<Marker, F: SystemParamFunction<Marker>> {
+    func: F,
+    state: Option<<F::Param as SystemParam>::State>,
+    meta: SystemMeta,
+    marker: PhantomData<fn() -> Marker>,
+}
+
+impl<Marker: 'static, F: SystemParamFunction<Marker>> System for FunctionSystem<Marker, F> {
+    fn name(&self) -> Cow<'static, str> {
+        self.meta.name.clone()
+    }
+
+    fn access(&self) -> &Access {
+        &self.meta.access
+    }
+
+    fn initialize(&mut self, world: &mut World) {
+        if self.state.is_none() {
+            // Everything already in the World counts as changed on the first run
+            self.meta.last_run = world.change_tick().relative_to(MAX_CHANGE_AGE);
+            self.state = Some(F::Param::init_state(world, &mut self.meta));
+        }
+    }
+
+    unsafe fn run_unsafe(&mut self, world: &World) {
+        self.meta.this_run = world.increment_change_tick();
+        let meta = &self.meta;
+        let state = self.state.as_mut().unwrap_or_else(|| panic!("{} was run before being initialized", meta.name));
+        let param = F::Param::get_param(state, meta, world);
+        self.func.run(param);
+        self.meta.last_run = self.meta.this_run;
+    }
+
+    fn apply_deferred(&mut self, world: &mut World) {
+        if let Some(state) = self.state.as_mut() {
+            F::Param::apply(state, world);
+        }
+    }
+
+    fn check_change_tick(&mut self, tick: Tick) {
+        self.meta.last_run.check_tick(tick);
+    }
+}
+
+/// Conversion into a System, implemented for Systems themselves and for functions of SystemParams
+pub trait IntoSystem<Marker> {
+    type System: System;
+
+    fn into_system(self) -> Self::System;
+}
+
+/// Marker for the IntoSystem impl of things that already are Systems
+pub struct IsSystem;
+
+impl<S: System> IntoSystem<IsSystem> for S {
+    type System = S;
+
+    fn into_system(self) -> S {
+        self
+    }
+}
+
+impl<Marker: 'static, F: SystemParamFunction<Marker>> IntoSystem<(Marker,)> for F {
+    type System = FunctionSystem<Marker, F>;
+
+    fn into_system(self) -> FunctionSystem<Marker, F> {
+        FunctionSystem {
+            func: self,
+            state: None,
+            meta: SystemMeta::new::<F>(),
+            marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::entity::query::With;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+
+    #[derive(Debug, PartialEq)]
+    struct Velocity(i32);
+
+    fn run<Marker>(world: &mut World, system: impl IntoSystem<Marker>) -> impl System {
+        let mut system = system.into_system();
+        system.initialize(world);
+        system.run(world);
+        system
+    }
+
+    fn movement(mut query: Query<(&mut Position, &Velocity)>) {
+        for (_, (mut position, velocity)) in query.iter_mut() {
+            position.0 += velocity.0;
+        }
+    }
+
+    #[test]
+    fn function_systems_get_their_parameters() {
+        let mut world = World::new();
+        let moving = world.spawn((Position(0), Velocity(2)));
+        let still = world.spawn((Position(5),));
+        let mut system = run(&mut world, movement);
+        system.run(&mut world);
+
+        assert_eq!(world.get::<Position>(moving), Some(&Position(4)));
+        assert_eq!(world.get::<Position>(still), Some(&Position(5)));
+        assert!(system.name().ends_with("movement"));
+
+        let position = world.components().id::<Position>().unwrap();
+        let velocity = world.components().id::<Velocity>().unwrap();
+        assert!(system.access().has_write(position));
+        assert!(system.access().has_read(velocity));
+    }
+
+    #[test]
+    fn query_contains_checks_the_filter_without_fetching() {
+        let mut world = World::new();
+        let moving = world.spawn((Position(0), Velocity(1)));
+        let still = world.spawn((Position(0),));
+        let gone = world.spawn((Position(0), Velocity(1)));
+        world.despawn(gone);
+
+        run(&mut world, move |query: Query<&mut Position, With<Velocity>>| {
+            assert!(query.contains(moving));
+            assert!(!query.contains(still));
+            assert!(!query.contains(gone));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "would alias a &mut")]
+    fn conflicting_parameters_panic_on_initialize() {
+        let mut world = World::new();
+        run(&mut world, |_: Query<&mut Position>, _: Query<&Position>| {});
+    }
+
+    #[test]
+    fn reading_and_writing_the_same_component_conflicts() {
+        let mut world = World::new();
+        let mut system = (|_: Query<&Position>, _: Query<&Velocity>| {}).into_system();
+        system.initialize(&mut world);
+        let mut other = (|_: Query<&mut Position>| {}).into_system();
+        other.initialize(&mut world);
+        assert!(!system.access().is_compatible(other.access()));
+    }
+}
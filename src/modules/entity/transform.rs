@@ -0,0 +1,357 @@
+use std::collections::HashSet;
+
+use crate::modules::utility::math::{ Mat4, Quat, Vec3 };
+
+use super::entity_map::Entity;
+use super::hierarchy::{ Children, Parent };
+use super::query::{ Changed, With };
+use super::reflect::{ FieldInfo, Reflect };
+use super::removal_detection::RemovedComponents;
+use super::system::Query;
+use super::value::{ Persist, Value, ValueError };
+use super::world::World;
+
+/// Label of `propagate_transforms`, for ordering other Systems around it
+pub const PROPAGATE_TRANSFORMS: &str = "propagate_transforms";
+
+/// Placement of an Entity relative to its Parent, or to the world if it has none
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn from_translation(translation: Vec3) -> Transform {
+        Transform {
+            translation,
+            ..Transform::IDENTITY
+        }
+    }
+
+    pub fn from_xyz(x: f32, y: f32, z: f32) -> Transform {
+        Transform::from_translation(Vec3::new(x, y, z))
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Transform {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Transform {
+        self.scale = scale;
+        self
+    }
+
+    pub fn compute_matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+/// Placement of an Entity in the world, written by `propagate_transforms` and read by the renderer
+///
+/// Entities need both a Transform and a GlobalTransform to take part in propagation
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GlobalTransform(Mat4);
+
+impl GlobalTransform {
+    pub fn matrix(&self) -> Mat4 {
+        self.0
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        self.0.translation()
+    }
+
+    /// The GlobalTransform of a child with the given local Transform
+    pub fn mul_transform(&self, transform: &Transform) -> GlobalTransform {
+        GlobalTransform(self.0 * transform.compute_matrix())
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.0.transform_point3(point)
+    }
+}
+
+impl From<Transform> for GlobalTransform {
+    fn from(transform: Transform) -> GlobalTransform {
+        GlobalTransform(transform.compute_matrix())
+    }
+}
+
+impl From<Mat4> for GlobalTransform {
+    fn from(matrix: Mat4) -> GlobalTransform {
+        GlobalTransform(matrix)
+    }
+}
+
+crate::reflect_struct!(Transform { translation: Vec3, rotation: Quat, scale: Vec3 });
+
+/// Stored as the 16 floats of the matrix, column by column
+impl Persist for GlobalTransform {
+    fn to_value(&self) -> Value {
+        self.0.to_cols_array().to_vec().to_value()
+    }
+
+    fn from_value(value: &Value) -> Result<GlobalTransform, ValueError> {
+        let floats = Vec::<f32>::from_value(value)?;
+        if floats.len() != 16 {
+            return Err(ValueError::expected("list of 16 floats", value));
+        }
+        let mut cols = [[0.0; 4]; 4];
+        for (col, chunk) in cols.iter_mut().zip(floats.chunks(4)) {
+            col.copy_from_slice(chunk);
+        }
+        Ok(GlobalTransform(Mat4 { cols }))
+    }
+}
+
+impl Reflect for GlobalTransform {
+    fn fields() -> &'static [FieldInfo] {
+        &[]
+    }
+}
+
+/// Registers the hook that gets a GlobalTransform added after its Transform computed, called by
+/// `World::new`
+pub fn register_transform_hooks(world: &mut World) {
+    world.component_hooks::<GlobalTransform>().on_add(|_, trigger, commands| {
+        let entity = trigger.entity;
+        // `propagate_transforms` only visits Entities whose Transform or Parent changed
+        commands.add(move |world| {
+            if let Some(mut transform) = world.get_mut::<Transform>(entity) {
+                transform.set_changed();
+            }
+        });
+    });
+}
+
+type TransformNode = (&'static Transform, &'static mut GlobalTransform, Option<&'static Children>);
+
+/// Any Entity, including the ones in a hierarchy that are missing a Transform or GlobalTransform
+type LinkNode = (Option<&'static Children>, Option<&'static Parent>);
+
+/// Recomputes the GlobalTransform of every Entity whose Transform or Parent changed, along with
+/// everything below it. Only those subtrees are walked, plus the ancestors of where they start to find
+/// the placement to start from
+///
+/// Entities that just lost their Parent count as changed too, their GlobalTransform was still relative
+/// to the old one. Entities missing a Transform or GlobalTransform are left alone, but their children
+/// are still visited and placed relative to the closest ancestor that has both
+///
+/// Runs in `CoreStage::PostUpdate` once added with `Schedule::add_transform_propagation`
+pub fn propagate_transforms(
+    changed: Query<&Transform, (Changed<Transform>, With<GlobalTransform>)>,
+    moved: Query<&Parent, Changed<Parent>>,
+    mut nodes: Query<TransformNode>,
+    links: Query<LinkNode>,
+    mut orphaned: RemovedComponents<Parent>,
+) {
+    let dirty: HashSet<Entity> = changed
+        .iter()
+        .map(|(entity, _)| entity)
+        .chain(moved.iter().map(|(entity, _)| entity))
+        .chain(orphaned.read().copied())
+        .collect();
+
+    // Walking down from the topmost dirty Entities covers every other one
+    let mut stack: Vec<(Entity, Option<GlobalTransform>)> = Vec::new();
+    'dirty: for &entity in &dirty {
+        let mut parent_global = None;
+        let mut ancestors = HashSet::new();
+        let mut ancestor = entity;
+        while let Some(parent) = links.get(ancestor).and_then(|(_, parent)| parent).map(Parent::get) {
+            // A cycle inserted by hand has no top to start from
+            if dirty.contains(&parent) || !ancestors.insert(parent) {
+                continue 'dirty;
+            }
+            if parent_global.is_none() {
+                parent_global = nodes.get_mut(parent).map(|(_, global, _)| *global);
+            }
+            ancestor = parent;
+        }
+        stack.push((entity, parent_global));
+    }
+
+    while let Some((entity, parent_global)) = stack.pop() {
+        let (global, children) = match nodes.get_mut(entity) {
+            Some((transform, mut global, children)) => {
+                *global = match parent_global {
+                    Some(parent_global) => parent_global.mul_transform(transform),
+                    None => GlobalTransform::from(*transform),
+                };
+                (Some(*global), children)
+            }
+            None => match links.get(entity) {
+                Some((children, _)) => (parent_global, children),
+                None => continue,
+            },
+        };
+        for &child in children.into_iter().flat_map(|children| children.iter()) {
+            stack.push((child, global));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::entity::system::{ IntoSystem, System };
+
+    fn placed(world: &mut World, x: f32) -> Entity {
+        world.spawn((Transform::from_xyz(x, 0.0, 0.0), GlobalTransform::default()))
+    }
+
+    fn x(world: &World, entity: Entity) -> f32 {
+        world.get::<GlobalTransform>(entity).unwrap().translation().x
+    }
+
+    fn propagation(world: &mut World) -> impl System {
+        let mut system = propagate_transforms.into_system();
+        system.initialize(world);
+        system
+    }
+
+    #[test]
+    fn children_are_placed_relative_to_their_parent() {
+        let mut world = World::new();
+        let root = placed(&mut world, 1.0);
+        let child = placed(&mut world, 2.0);
+        let grandchild = placed(&mut world, 4.0);
+        world.attach(child, root).unwrap();
+        world.attach(grandchild, child).unwrap();
+        let mut system = propagation(&mut world);
+
+        system.run(&mut world);
+        assert_eq!([x(&world, root), x(&world, child), x(&world, grandchild)], [1.0, 3.0, 7.0]);
+
+        world.get_mut::<Transform>(root).unwrap().translation.x = 10.0;
+        system.run(&mut world);
+        assert_eq!([x(&world, root), x(&world, child), x(&world, grandchild)], [10.0, 12.0, 16.0]);
+    }
+
+    #[test]
+    fn untouched_subtrees_are_skipped() {
+        let mut world = World::new();
+        let root = placed(&mut world, 1.0);
+        let child = placed(&mut world, 2.0);
+        world.attach(child, root).unwrap();
+        let mut system = propagation(&mut world);
+        system.run(&mut world);
+
+        *world.get_mut::<GlobalTransform>(child).unwrap().bypass_change_detection() = GlobalTransform::default();
+        system.run(&mut world);
+        assert_eq!(x(&world, child), 0.0);
+    }
+
+    #[test]
+    fn reparented_and_detached_entities_are_recomputed() {
+        let mut world = World::new();
+        let first = placed(&mut world, 1.0);
+        let second = placed(&mut world, 5.0);
+        let child = placed(&mut world, 2.0);
+        world.attach(child, first).unwrap();
+        let mut system = propagation(&mut world);
+        system.run(&mut world);
+        assert_eq!(x(&world, child), 3.0);
+
+        world.reparent(child, second).unwrap();
+        system.run(&mut world);
+        assert_eq!(x(&world, child), 7.0);
+
+        world.detach(child);
+        system.run(&mut world);
+        assert_eq!(x(&world, child), 2.0);
+    }
+
+    #[test]
+    fn entities_without_transforms_pass_the_parent_placement_down() {
+        let mut world = World::new();
+        let root = placed(&mut world, 1.0);
+        let group = world.spawn(());
+        let child = placed(&mut world, 2.0);
+        let bare_root = world.spawn(());
+        let loose = placed(&mut world, 3.0);
+        world.attach(group, root).unwrap();
+        world.attach(child, group).unwrap();
+        world.attach(loose, bare_root).unwrap();
+        let mut system = propagation(&mut world);
+
+        system.run(&mut world);
+        assert_eq!(x(&world, child), 3.0);
+        assert_eq!(x(&world, loose), 3.0);
+        assert!(world.get::<GlobalTransform>(group).is_none());
+
+        world.get_mut::<Transform>(root).unwrap().translation.x = 4.0;
+        system.run(&mut world);
+        assert_eq!(x(&world, child), 6.0);
+    }
+
+    #[test]
+    fn only_the_changed_subtree_is_recomputed() {
+        let mut world = World::new();
+        let root = placed(&mut world, 1.0);
+        let changed = placed(&mut world, 2.0);
+        let below = placed(&mut world, 4.0);
+        let sibling = placed(&mut world, 8.0);
+        world.attach(changed, root).unwrap();
+        world.attach(below, changed).unwrap();
+        world.attach(sibling, root).unwrap();
+        let mut system = propagation(&mut world);
+        system.run(&mut world);
+
+        let moved = GlobalTransform::from(Transform::from_xyz(16.0, 0.0, 0.0));
+        *world.get_mut::<GlobalTransform>(root).unwrap().bypass_change_detection() = moved;
+        *world.get_mut::<GlobalTransform>(sibling).unwrap().bypass_change_detection() = GlobalTransform::default();
+        world.get_mut::<Transform>(changed).unwrap().translation.x = 3.0;
+        system.run(&mut world);
+        // Starts from the root's stored placement without recomputing it or its other children
+        assert_eq!([x(&world, root), x(&world, sibling)], [16.0, 0.0]);
+        assert_eq!([x(&world, changed), x(&world, below)], [19.0, 23.0]);
+    }
+
+    #[test]
+    fn global_transforms_added_later_are_computed() {
+        let mut world = World::new();
+        let root = placed(&mut world, 1.0);
+        let child = world.spawn((Transform::from_xyz(2.0, 0.0, 0.0),));
+        world.attach(child, root).unwrap();
+        let mut system = propagation(&mut world);
+        system.run(&mut world);
+
+        world.insert(child, GlobalTransform::default());
+        system.run(&mut world);
+        assert_eq!(x(&world, child), 3.0);
+    }
+
+    #[test]
+    fn moving_an_entity_without_transforms_moves_its_children() {
+        let mut world = World::new();
+        let first = placed(&mut world, 1.0);
+        let second = placed(&mut world, 5.0);
+        let group = world.spawn(());
+        let child = placed(&mut world, 2.0);
+        world.attach(group, first).unwrap();
+        world.attach(child, group).unwrap();
+        let mut system = propagation(&mut world);
+        system.run(&mut world);
+        assert_eq!(x(&world, child), 3.0);
+
+        world.reparent(group, second).unwrap();
+        system.run(&mut world);
+        assert_eq!(x(&world, child), 7.0);
+    }
+}
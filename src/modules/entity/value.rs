@@ -0,0 +1,300 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use crate::modules::utility::math::{ Quat, Vec3 };
+
+use super::entity_map::Entity;
+
+/// Type erased copy of a Component or Resource, what snapshots are made of
+///
+/// Entity references get a variant of their own so they can be remapped when a snapshot is loaded
+/// into a World that hands out different Entities
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Entity(Entity),
+    List(Vec<Value>),
+    /// Named fields in declaration order
+    Struct(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Name of the variant, for error messages
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Unit => "unit",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Entity(_) => "entity",
+            Value::List(_) => "list",
+            Value::Struct(_) => "struct",
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Struct(fields) => fields.iter().find(|(field, _)| field == name).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn field_mut(&mut self, name: &str) -> Option<&mut Value> {
+        match self {
+            Value::Struct(fields) => fields.iter_mut().find(|(field, _)| field == name).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Converts the named field of a Struct, for implementing `Persist::from_value`
+    pub fn read_field<T: Persist>(&self, name: &str) -> Result<T, ValueError> {
+        match self {
+            Value::Struct(_) => match self.field(name) {
+                Some(value) => T::from_value(value),
+                None => Err(ValueError::MissingField(name.to_string())),
+            },
+            other => Err(ValueError::expected("struct", other)),
+        }
+    }
+
+    /// Replaces every Entity anywhere inside the Value
+    pub fn map_entities(&mut self, f: &mut dyn FnMut(Entity) -> Entity) {
+        match self {
+            Value::Entity(entity) => *entity = f(*entity),
+            Value::List(items) => items.iter_mut().for_each(|item| item.map_entities(f)),
+            Value::Struct(fields) => fields.iter_mut().for_each(|(_, value)| value.map_entities(f)),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValueError {
+    /// The Value has a different shape than the type being read from it
+    Expected { expected: &'static str, found: &'static str },
+    MissingField(String),
+    /// The number does not fit the integer type being read
+    OutOfRange { value: i64, target: &'static str },
+}
+
+impl ValueError {
+    pub fn expected(expected: &'static str, found: &Value) -> ValueError {
+        ValueError::Expected { expected, found: found.kind() }
+    }
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueError::Expected { expected, found } => write!(f, "expected {}, found {}", expected, found),
+            ValueError::MissingField(name) => write!(f, "missing field {}", name),
+            ValueError::OutOfRange { value, target } => write!(f, "{} does not fit in {}", value, target),
+        }
+    }
+}
+
+impl Error for ValueError {}
+
+/// Conversion to and from Value, what a type needs to be saved in a snapshot
+pub trait Persist: Sized {
+    fn to_value(&self) -> Value;
+
+    fn from_value(value: &Value) -> Result<Self, ValueError>;
+}
+
+impl Persist for () {
+    fn to_value(&self) -> Value {
+        Value::Unit
+    }
+
+    fn from_value(value: &Value) -> Result<(), ValueError> {
+        match value {
+            Value::Unit => Ok(()),
+            other => Err(ValueError::expected("unit", other)),
+        }
+    }
+}
+
+impl Persist for bool {
+    fn to_value(&self) -> Value {
+        Value::Bool(*self)
+    }
+
+    fn from_value(value: &Value) -> Result<bool, ValueError> {
+        match value {
+            Value::Bool(value) => Ok(*value),
+            other => Err(ValueError::expected("bool", other)),
+        }
+    }
+}
+
+macro_rules! persist_int {
+    ($($t:ty),*) => {$(
+        impl Persist for $t {
+            fn to_value(&self) -> Value {
+                Value::Int(*self as i64)
+            }
+
+            fn from_value(value: &Value) -> Result<$t, ValueError> {
+                match value {
+                    Value::Int(value) => <$t>::try_from(*value).map_err(|_| ValueError::OutOfRange {
+                        value: *value,
+                        target: stringify!($t),
+                    }),
+                    other => Err(ValueError::expected("int", other)),
+                }
+            }
+        }
+    )*};
+}
+
+// u64 and usize are stored as their bit pattern so the full range survives the trip through i64
+persist_int!(i8, i16, i32, i64, u8, u16, u32);
+
+impl Persist for u64 {
+    fn to_value(&self) -> Value {
+        Value::Int(*self as i64)
+    }
+
+    fn from_value(value: &Value) -> Result<u64, ValueError> {
+        match value {
+            Value::Int(value) => Ok(*value as u64),
+            other => Err(ValueError::expected("int", other)),
+        }
+    }
+}
+
+impl Persist for usize {
+    fn to_value(&self) -> Value {
+        (*self as u64).to_value()
+    }
+
+    fn from_value(value: &Value) -> Result<usize, ValueError> {
+        let wide = u64::from_value(value)?;
+        usize::try_from(wide).map_err(|_| ValueError::OutOfRange {
+            value: wide as i64,
+            target: "usize",
+        })
+    }
+}
+
+impl Persist for f32 {
+    /// Goes through the shortest decimal form so the text format shows `0.1` rather than the f64
+    /// closest to the f32 closest to 0.1, it still converts back to the exact same f32
+    fn to_value(&self) -> Value {
+        Value::Float(self.to_string().parse().unwrap())
+    }
+
+    fn from_value(value: &Value) -> Result<f32, ValueError> {
+        f64::from_value(value).map(|value| value as f32)
+    }
+}
+
+/// Ints are accepted as well, hand written text snapshots tend to leave off the `.0`
+impl Persist for f64 {
+    fn to_value(&self) -> Value {
+        Value::Float(*self)
+    }
+
+    fn from_value(value: &Value) -> Result<f64, ValueError> {
+        match value {
+            Value::Float(value) => Ok(*value),
+            Value::Int(value) => Ok(*value as f64),
+            other => Err(ValueError::expected("float", other)),
+        }
+    }
+}
+
+impl Persist for String {
+    fn to_value(&self) -> Value {
+        Value::String(self.clone())
+    }
+
+    fn from_value(value: &Value) -> Result<String, ValueError> {
+        match value {
+            Value::String(value) => Ok(value.clone()),
+            other => Err(ValueError::expected("string", other)),
+        }
+    }
+}
+
+impl Persist for Entity {
+    fn to_value(&self) -> Value {
+        Value::Entity(*self)
+    }
+
+    fn from_value(value: &Value) -> Result<Entity, ValueError> {
+        match value {
+            Value::Entity(entity) => Ok(*entity),
+            other => Err(ValueError::expected("entity", other)),
+        }
+    }
+}
+
+impl<T: Persist> Persist for Vec<T> {
+    fn to_value(&self) -> Value {
+        Value::List(self.iter().map(Persist::to_value).collect())
+    }
+
+    fn from_value(value: &Value) -> Result<Vec<T>, ValueError> {
+        match value {
+            Value::List(items) => items.iter().map(T::from_value).collect(),
+            other => Err(ValueError::expected("list", other)),
+        }
+    }
+}
+
+/// None is stored as an empty List and Some as a List of one, so `Option<()>` stays unambiguous
+impl<T: Persist> Persist for Option<T> {
+    fn to_value(&self) -> Value {
+        Value::List(self.iter().map(Persist::to_value).collect())
+    }
+
+    fn from_value(value: &Value) -> Result<Option<T>, ValueError> {
+        match value {
+            Value::List(items) if items.is_empty() => Ok(None),
+            Value::List(items) if items.len() == 1 => T::from_value(&items[0]).map(Some),
+            other => Err(ValueError::expected("list of at most one", other)),
+        }
+    }
+}
+
+impl Persist for Vec3 {
+    fn to_value(&self) -> Value {
+        Value::Struct(vec![
+            ("x".to_string(), self.x.to_value()),
+            ("y".to_string(), self.y.to_value()),
+            ("z".to_string(), self.z.to_value()),
+        ])
+    }
+
+    fn from_value(value: &Value) -> Result<Vec3, ValueError> {
+        Ok(Vec3::new(value.read_field("x")?, value.read_field("y")?, value.read_field("z")?))
+    }
+}
+
+impl Persist for Quat {
+    fn to_value(&self) -> Value {
+        Value::Struct(vec![
+            ("x".to_string(), self.x.to_value()),
+            ("y".to_string(), self.y.to_value()),
+            ("z".to_string(), self.z.to_value()),
+            ("w".to_string(), self.w.to_value()),
+        ])
+    }
+
+    fn from_value(value: &Value) -> Result<Quat, ValueError> {
+        Ok(Quat {
+            x: value.read_field("x")?,
+            y: value.read_field("y")?,
+            z: value.read_field("z")?,
+            w: value.read_field("w")?,
+        })
+    }
+}
@@ -0,0 +1,755 @@
+use std::any::{ type_name, Any };
+use std::collections::HashMap;
+use std::mem::replace;
+use std::sync::atomic::{ AtomicU32, AtomicUsize, Ordering };
+
+use super::archetype::{ ArchetypeId, Archetypes, AnyColumn, Column, EntityLocation };
+use super::bundle::Bundle;
+use super::change_detection::{ Mut, Tick };
+use super::command::CommandQueue;
+use super::component::{ Component, ComponentId, Components };
+use super::entity_map::{ Entity, EntityMap, RecyclePolicy };
+use super::hierarchy::register_hierarchy_hooks;
+use super::observer::{ Lifecycle, Observers };
+use super::query::{ QueryFilter, QueryState, WorldQuery };
+use super::reflect::TypeRegistry;
+use super::removal_detection::RemovedComponentEvents;
+use super::resource::{ Resource, Resources };
+use super::storage::{ AnyStorage, BoxedStorage, ComponentCell, StorageType };
+use super::transform::register_transform_hooks;
+
+/// Holds every Entity and all of the Components attached to them
+///
+/// The EntityMap hands out Entity handles and remembers where each Entity lives. Entities with the
+/// same set of Components share an Archetype whose table keeps one contiguous column per Component,
+/// so iterating a combination of Components only has to visit the matching tables. Components
+/// registered with any other StorageType keep their values in a per type storage instead.
+/// Singletons that are not tied to any Entity live alongside as Resources
+pub struct World {
+    id: WorldId,
+    entities: EntityMap<EntityLocation>,
+    components: Components,
+    archetypes: Archetypes,
+    storages: HashMap<ComponentId, Box<dyn AnyStorage>>,
+    resources: Resources,
+    change_tick: AtomicU32,
+    last_change_tick: Tick,
+    removed: RemovedComponentEvents,
+    type_registry: TypeRegistry,
+    observers: Observers,
+}
+
+/// Unique per World so state cached against one World cannot be used with another
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WorldId(usize);
+
+static NEXT_WORLD_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl Default for World {
+    fn default() -> World {
+        World::new()
+    }
+}
+
+impl World {
+    pub fn new() -> World {
+        let components = Components::default();
+        let archetypes = Archetypes::new(&components);
+        let mut world = World {
+            id: WorldId(NEXT_WORLD_ID.fetch_add(1, Ordering::Relaxed)),
+            entities: EntityMap::new(),
+            components,
+            archetypes,
+            storages: HashMap::new(),
+            resources: Resources::default(),
+            change_tick: AtomicU32::new(1),
+            last_change_tick: Tick::new(0),
+            removed: RemovedComponentEvents::default(),
+            type_registry: TypeRegistry::default(),
+            observers: Observers::default(),
+        };
+        register_hierarchy_hooks(&mut world);
+        register_transform_hooks(&mut world);
+        world
+    }
+
+    /// Spawns an Entity with every Component of the Bundle, e.g. `world.spawn((Transform::IDENTITY, Velocity))`
+    ///
+    /// The Entity goes straight into the Archetype holding the whole Bundle
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> Entity {
+        self.flush();
+        let ids = self.bundle_ids::<B>();
+        let to = self.archetypes.with_components(ArchetypeId::EMPTY, &ids, &self.components);
+        let archetype = self.archetypes.get_mut(to);
+        let location = EntityLocation {
+            archetype: to,
+            row: archetype.len(),
+        };
+        let entity = self.entities.insert(location);
+        archetype.push_entity(entity);
+
+        let tick = self.change_tick();
+        bundle.write(&mut BundleWriter {
+            world: self,
+            entity,
+            location,
+            had: ArchetypeId::EMPTY,
+            ids: &ids,
+            next: 0,
+            tick,
+        });
+        self.on_inserted(entity, &ids, &ids, CommandQueue::default());
+        entity
+    }
+
+    pub fn spawn_empty(&mut self) -> Entity {
+        self.flush();
+        let archetype = self.archetypes.get_mut(ArchetypeId::EMPTY);
+        let entity = self.entities.insert(EntityLocation {
+            archetype: ArchetypeId::EMPTY,
+            row: archetype.len(),
+        });
+        archetype.push_entity(entity);
+        entity
+    }
+
+    /// Hands out an Entity without needing `&mut World`, it is spawned without any Components by the
+    /// next `flush`. Until then it does not exist
+    ///
+    /// Any number of threads can reserve at once, freed slots are reused before new ones are added
+    pub fn reserve_entity(&self) -> Entity {
+        self.entities.reserve_entity()
+    }
+
+    /// Spawns every reserved Entity, called before anything that changes which Entities exist
+    pub fn flush(&mut self) {
+        if !self.entities.has_reserved() {
+            return;
+        }
+        let archetypes = &mut self.archetypes;
+        self.entities.flush_reserved(|entity| EntityLocation {
+            archetype: ArchetypeId::EMPTY,
+            row: archetypes.get_mut(ArchetypeId::EMPTY).push_entity(entity),
+        });
+    }
+
+    /// Removes the Entity along with all of its Components, returns false if it was already gone
+    ///
+    /// The Entity is dropped from its parent's Children and its children are left without a parent, use
+    /// `despawn_recursive` to despawn them as well
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        self.flush();
+        let location = match self.location(entity) {
+            Some(location) => location,
+            None => return false,
+        };
+        let mut queue = CommandQueue::default();
+        let components = self.archetypes.get(location.archetype).components();
+        self.trigger(Lifecycle::Replace, entity, components, &mut queue);
+        self.trigger(Lifecycle::Remove, entity, components, &mut queue);
+        self.entities.remove(entity);
+
+        let archetype = self.archetypes.get_mut(location.archetype);
+        for component in archetype.components() {
+            if let Some(storage) = self.storages.get_mut(component) {
+                storage.remove_entity(entity);
+            }
+            self.removed.send(*component, entity);
+        }
+        self.removed.send_despawned(entity);
+        if let Some(moved) = archetype.swap_remove(location.row) {
+            self.set_row(moved, location.row);
+        }
+        queue.apply(self);
+        true
+    }
+
+    pub fn id(&self) -> WorldId {
+        self.id
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains(entity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Every Entity in the World, ordered by index
+    pub fn iter_entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.entities.iter().map(|(entity, _)| entity)
+    }
+
+    pub fn location(&self, entity: Entity) -> Option<EntityLocation> {
+        self.entities.get(entity).copied()
+    }
+
+    pub fn components(&self) -> &Components {
+        &self.components
+    }
+
+    pub fn archetypes(&self) -> &Archetypes {
+        &self.archetypes
+    }
+
+    /// Returns the id for C, registering it with the default StorageType if it is new
+    pub fn init_component<C: Component>(&mut self) -> ComponentId {
+        self.components.init::<C>()
+    }
+
+    /// Picks the storage backend for a Component type, this has to happen before the first insert
+    ///
+    /// Returns false if the type is already known, in which case it is left untouched
+    pub fn register_component<C: Component>(&mut self, storage_type: StorageType) -> bool {
+        self.components.register::<C>(storage_type).is_some()
+    }
+
+    /// Component hooks and observers, see `component_hooks` and `observe`
+    pub fn observers(&self) -> &Observers {
+        &self.observers
+    }
+
+    pub fn observers_mut(&mut self) -> &mut Observers {
+        &mut self.observers
+    }
+
+    /// Component types registered for reflection, see `register_type`
+    pub fn type_registry(&self) -> &TypeRegistry {
+        &self.type_registry
+    }
+
+    pub fn type_registry_mut(&mut self) -> &mut TypeRegistry {
+        &mut self.type_registry
+    }
+
+    /// The tick changes made right now are stamped with
+    pub fn change_tick(&self) -> Tick {
+        Tick::new(self.change_tick.load(Ordering::Acquire))
+    }
+
+    /// Advances the change tick, returning the one it replaced. Every System run takes a tick of its own
+    pub fn increment_change_tick(&self) -> Tick {
+        Tick::new(self.change_tick.fetch_add(1, Ordering::AcqRel))
+    }
+
+    /// What changes made outside of Systems are compared against, e.g. by `get_mut` and `QueryState::iter`
+    pub fn last_change_tick(&self) -> Tick {
+        self.last_change_tick
+    }
+
+    /// Starts a new frame for change and removal detection, called by `Schedule::run`
+    ///
+    /// Removals are kept for two frames, see RemovedComponents. Also counts towards the Quarantine of
+    /// despawned Entities, see `set_recycle_policy`
+    pub fn clear_trackers(&mut self) {
+        self.last_change_tick = self.increment_change_tick();
+        self.removed.update();
+        self.flush();
+        self.entities.advance_frame();
+    }
+
+    /// Decides when the slots of despawned Entities are handed out again, Lifo unless set otherwise
+    pub fn set_recycle_policy(&mut self, policy: RecyclePolicy) {
+        self.flush();
+        self.entities.set_recycle_policy(policy);
+    }
+
+    pub fn recycle_policy(&self) -> RecyclePolicy {
+        self.entities.recycle_policy()
+    }
+
+    /// Entities that recently lost a Component or were despawned
+    pub fn removed_components(&self) -> &RemovedComponentEvents {
+        &self.removed
+    }
+
+    pub fn removed_components_mut(&mut self) -> &mut RemovedComponentEvents {
+        &mut self.removed
+    }
+
+    /// Clamps every stored change tick so none of them grows too old to compare against the current
+    /// one, see `Schedule::run`
+    pub fn check_change_ticks(&mut self) {
+        let tick = self.change_tick();
+        for archetype in self.archetypes.iter_mut() {
+            archetype.check_change_ticks(tick);
+        }
+        for storage in self.storages.values_mut() {
+            storage.check_change_ticks(tick);
+        }
+        self.last_change_tick.check_tick(tick);
+    }
+
+    /// Stores a Resource, returning the one of the same type it replaced
+    pub fn insert_resource<R: Resource>(&mut self, value: R) -> Option<R> {
+        self.resources.insert(value)
+    }
+
+    pub fn remove_resource<R: Resource>(&mut self) -> Option<R> {
+        self.resources.remove::<R>()
+    }
+
+    pub fn contains_resource<R: Resource>(&self) -> bool {
+        self.resources.contains::<R>()
+    }
+
+    pub fn resource<R: Resource>(&self) -> Option<&R> {
+        self.resources.get::<R>()
+    }
+
+    pub fn resource_mut<R: Resource>(&mut self) -> Option<&mut R> {
+        self.resources.get_mut::<R>()
+    }
+
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    /// Builds the state for iterating every Entity that matches Q, e.g. `(&Position, &mut Velocity)`
+    pub fn query<Q: WorldQuery>(&mut self) -> QueryState<Q, ()> {
+        QueryState::new(self)
+    }
+
+    /// Same as `query` but only visiting Entities that pass F, e.g. `(With<Player>, Without<Frozen>)`
+    pub fn query_filtered<Q: WorldQuery, F: QueryFilter>(&mut self) -> QueryState<Q, F> {
+        QueryState::new(self)
+    }
+
+    /// Attaches a Component to the Entity, returning the one it replaced
+    ///
+    /// Adding a new Component type moves the Entity to the matching Archetype.
+    /// Panics if the Entity has been despawned
+    pub fn insert<C: Component>(&mut self, entity: Entity, component: C) -> Option<C> {
+        self.flush();
+        let location = match self.location(entity) {
+            Some(location) => location,
+            None => panic!("cannot insert a component on {}, it does not exist", entity),
+        };
+        let id = self.components.init::<C>();
+        let tick = self.change_tick();
+
+        if self.archetypes.get(location.archetype).contains(id) {
+            let mut queue = CommandQueue::default();
+            self.trigger(Lifecycle::Replace, entity, &[id], &mut queue);
+            let replaced = self.replace_component(entity, location, id, component, tick);
+            self.on_inserted(entity, &[], &[id], queue);
+            return Some(replaced);
+        }
+
+        let to = self.archetypes.with_component(location.archetype, id, &self.components);
+        let location = self.move_entity(entity, location, to, &mut |_, _, _| {
+            unreachable!("adding a component never leaves a column behind")
+        });
+        self.push_component(entity, location, id, component, tick);
+        self.on_inserted(entity, &[id], &[id], CommandQueue::default());
+        None
+    }
+
+    /// Attaches every Component of the Bundle, replacing the ones the Entity already has
+    ///
+    /// The Entity moves to its new Archetype once rather than once per Component.
+    /// Panics if the Entity has been despawned
+    pub fn insert_bundle<B: Bundle>(&mut self, entity: Entity, bundle: B) {
+        self.flush();
+        let location = match self.location(entity) {
+            Some(location) => location,
+            None => panic!("cannot insert a bundle on {}, it does not exist", entity),
+        };
+        let ids = self.bundle_ids::<B>();
+        let had = self.archetypes.get(location.archetype);
+        let (replaced, added): (Vec<ComponentId>, Vec<ComponentId>) = ids.iter().partition(|&&id| had.contains(id));
+        let mut queue = CommandQueue::default();
+        self.trigger(Lifecycle::Replace, entity, &replaced, &mut queue);
+
+        let to = self.archetypes.with_components(location.archetype, &ids, &self.components);
+        let new_location = if to == location.archetype {
+            location
+        } else {
+            self.move_entity(entity, location, to, &mut |_, _, _| {
+                unreachable!("adding components never leaves a column behind")
+            })
+        };
+
+        let tick = self.change_tick();
+        bundle.write(&mut BundleWriter {
+            world: self,
+            entity,
+            location: new_location,
+            had: location.archetype,
+            ids: &ids,
+            next: 0,
+            tick,
+        });
+        self.on_inserted(entity, &added, &ids, queue);
+    }
+
+    /// Runs the Add hooks and observers for `added` and then the Insert ones for `inserted`, applying
+    /// whatever Commands they recorded after the ones already in `queue`
+    fn on_inserted(&mut self, entity: Entity, added: &[ComponentId], inserted: &[ComponentId], mut queue: CommandQueue) {
+        self.trigger(Lifecycle::Add, entity, added, &mut queue);
+        self.trigger(Lifecycle::Insert, entity, inserted, &mut queue);
+        queue.apply(self);
+    }
+
+    /// Swaps the value of a Component the Entity already has, marking it changed
+    fn replace_component<C: Component>(
+        &mut self,
+        entity: Entity,
+        location: EntityLocation,
+        id: ComponentId,
+        component: C,
+        tick: Tick,
+    ) -> C {
+        let (value, ticks) = if self.archetypes.get(location.archetype).has_column(id) {
+            self.archetypes
+                .get_mut(location.archetype)
+                .column_mut::<C>(id)
+                .expect("component column registered under the wrong type")
+                .get_with_ticks_mut(location.row)
+                .expect("entity location out of bounds")
+        } else {
+            self.storage_mut::<C>(id)
+                .get_mut(entity)
+                .expect("archetype and component storage disagree")
+                .parts_mut()
+        };
+        ticks.set_changed(tick);
+        replace(value, component)
+    }
+
+    /// Stores a Component the Entity has just gained, after it was moved to `location`
+    fn push_component<C: Component>(
+        &mut self,
+        entity: Entity,
+        location: EntityLocation,
+        id: ComponentId,
+        component: C,
+        tick: Tick,
+    ) {
+        if self.archetypes.get(location.archetype).has_column(id) {
+            self.archetypes
+                .get_mut(location.archetype)
+                .column_mut::<C>(id)
+                .expect("component column registered under the wrong type")
+                .push(component, tick);
+        } else {
+            self.storage_mut::<C>(id).insert(entity, ComponentCell::new(component, tick));
+        }
+    }
+
+    /// Registers the Components of B, panics if it has the same one twice
+    fn bundle_ids<B: Bundle>(&mut self) -> Vec<ComponentId> {
+        let mut ids = Vec::new();
+        B::component_ids(&mut self.components, &mut ids);
+        let mut sorted = ids.clone();
+        sorted.sort_unstable();
+        if sorted.windows(2).any(|pair| pair[0] == pair[1]) {
+            panic!("{} contains the same component more than once", type_name::<B>());
+        }
+        ids
+    }
+
+    pub fn get<C: Component>(&self, entity: Entity) -> Option<&C> {
+        let location = self.location(entity)?;
+        let id = self.components.id::<C>()?;
+        let archetype = self.archetypes.get(location.archetype);
+        if !archetype.contains(id) {
+            return None;
+        }
+
+        match archetype.column::<C>(id) {
+            Some(column) => column.get(location.row),
+            None => self.storage::<C>(id)?.get(entity).map(|cell| unsafe { cell.value().deref() }),
+        }
+    }
+
+    /// Writing through the returned Mut marks the Component changed
+    pub fn get_mut<C: Component>(&mut self, entity: Entity) -> Option<Mut<'_, C>> {
+        let location = self.location(entity)?;
+        let id = self.components.id::<C>()?;
+        if !self.archetypes.get(location.archetype).contains(id) {
+            return None;
+        }
+
+        let last_run = self.last_change_tick;
+        let this_run = self.change_tick();
+        let (value, ticks) = if self.archetypes.get(location.archetype).has_column(id) {
+            self.archetypes
+                .get_mut(location.archetype)
+                .column_mut::<C>(id)?
+                .get_with_ticks_mut(location.row)?
+        } else {
+            self.storage_mut::<C>(id).get_mut(entity)?.parts_mut()
+        };
+        Some(Mut::new(value, ticks, last_run, this_run))
+    }
+
+    /// Detaches a Component from the Entity, moving the Entity to the matching Archetype
+    pub fn remove<C: Component>(&mut self, entity: Entity) -> Option<C> {
+        self.flush();
+        let location = self.location(entity)?;
+        let id = self.components.id::<C>()?;
+        if !self.archetypes.get(location.archetype).contains(id) {
+            return None;
+        }
+
+        let mut queue = CommandQueue::default();
+        self.trigger(Lifecycle::Replace, entity, &[id], &mut queue);
+        self.trigger(Lifecycle::Remove, entity, &[id], &mut queue);
+
+        let to = self.archetypes.without_component(location.archetype, id, &self.components);
+        let mut value = None;
+        self.move_entity(entity, location, to, &mut |component, column, row| {
+            debug_assert_eq!(component, id);
+            let column = column
+                .as_any_mut()
+                .downcast_mut::<Column<C>>()
+                .expect("component column registered under the wrong type");
+            value = Some(column.swap_remove(row));
+        });
+
+        self.removed.send(id, entity);
+        let value = match value {
+            Some(value) => Some(value),
+            None => self.storage_mut::<C>(id).remove(entity).map(ComponentCell::into_inner),
+        };
+        queue.apply(self);
+        value
+    }
+
+    /// Detaches every Component of the Bundle the Entity has, moving it between Archetypes only once
+    ///
+    /// Returns the Bundle if the Entity had all of it, whatever part of it the Entity had is removed
+    /// and dropped otherwise
+    pub fn remove_bundle<B: Bundle>(&mut self, entity: Entity) -> Option<B> {
+        self.flush();
+        let location = self.location(entity)?;
+        let ids = self.bundle_ids::<B>();
+        let archetype = self.archetypes.get(location.archetype);
+        let had: Vec<ComponentId> = ids.into_iter().filter(|&id| archetype.contains(id)).collect();
+
+        let mut queue = CommandQueue::default();
+        self.trigger(Lifecycle::Replace, entity, &had, &mut queue);
+        self.trigger(Lifecycle::Remove, entity, &had, &mut queue);
+
+        let mut columns = HashMap::new();
+        if !had.is_empty() {
+            let to = self.archetypes.without_components(location.archetype, &had, &self.components);
+            self.move_entity(entity, location, to, &mut |component, column, row| {
+                columns.insert(component, column.swap_remove_any(row));
+            });
+            for &id in &had {
+                self.removed.send(id, entity);
+            }
+        }
+        let bundle = B::take(&mut BundleTaker {
+            world: self,
+            entity,
+            had,
+            columns,
+        });
+        queue.apply(self);
+        bundle
+    }
+
+    /// Moves the Entity's row to another Archetype and keeps every affected location up to date
+    fn move_entity(
+        &mut self,
+        entity: Entity,
+        location: EntityLocation,
+        to: ArchetypeId,
+        on_missing: &mut dyn FnMut(ComponentId, &mut dyn AnyColumn, usize),
+    ) -> EntityLocation {
+        let (row, moved) = self.archetypes.move_row(location.archetype, location.row, to, on_missing);
+        if let Some(moved) = moved {
+            self.set_row(moved, location.row);
+        }
+
+        let new_location = EntityLocation { archetype: to, row };
+        *self.entities.get_mut(entity).expect("moving an entity that does not exist") = new_location;
+        new_location
+    }
+
+    fn set_row(&mut self, entity: Entity, row: usize) {
+        self.entities
+            .get_mut(entity)
+            .expect("archetype holds an entity that does not exist")
+            .row = row;
+    }
+
+    /// The out of table storage for a Component, None for Table Components or before the first insert
+    pub fn storage<C: Component>(&self, id: ComponentId) -> Option<&BoxedStorage<ComponentCell<C>>> {
+        let storage = self.storages.get(&id)?;
+        Some(storage
+            .as_any()
+            .downcast_ref::<BoxedStorage<ComponentCell<C>>>()
+            .expect("component storage registered under the wrong type"))
+    }
+
+    fn storage_mut<C: Component>(&mut self, id: ComponentId) -> &mut BoxedStorage<ComponentCell<C>> {
+        let components = &self.components;
+        self.storages
+            .entry(id)
+            .or_insert_with(|| components.info(id).new_storage())
+            .as_any_mut()
+            .downcast_mut::<BoxedStorage<ComponentCell<C>>>()
+            .expect("component storage registered under the wrong type")
+    }
+}
+
+/// Stores the Components of a Bundle one at a time, see `Bundle::write`
+pub struct BundleWriter<'w> {
+    world: &'w mut World,
+    entity: Entity,
+    /// Where the Entity lives now that it has every Component of the Bundle
+    location: EntityLocation,
+    /// The Archetype it came from, Components it already had there get replaced
+    had: ArchetypeId,
+    ids: &'w [ComponentId],
+    next: usize,
+    tick: Tick,
+}
+
+impl<'w> BundleWriter<'w> {
+    /// Stores the next Component of the Bundle, they have to come in the order of `Bundle::component_ids`
+    pub fn write<C: Component>(&mut self, component: C) {
+        let id = self.ids[self.next];
+        self.next += 1;
+        debug_assert_eq!(self.world.components.id::<C>(), Some(id), "bundle wrote its components out of order");
+
+        if self.world.archetypes.get(self.had).contains(id) {
+            self.world.replace_component(self.entity, self.location, id, component, self.tick);
+        } else {
+            self.world.push_component(self.entity, self.location, id, component, self.tick);
+        }
+    }
+}
+
+/// Hands out the Components removed along with a Bundle, see `Bundle::take`
+pub struct BundleTaker<'w> {
+    world: &'w mut World,
+    entity: Entity,
+    /// Components of the Bundle the Entity had and that have not been taken yet
+    had: Vec<ComponentId>,
+    /// Values of the Table Components among them, already out of their columns
+    columns: HashMap<ComponentId, Box<dyn Any>>,
+}
+
+impl<'w> BundleTaker<'w> {
+    /// None if the Entity did not have the Component
+    pub fn take<C: Component>(&mut self) -> Option<C> {
+        let id = self.world.components.id::<C>()?;
+        let position = self.had.iter().position(|&had| had == id)?;
+        self.had.swap_remove(position);
+        match self.columns.remove(&id) {
+            Some(value) => Some(*value.downcast::<C>().expect("component column registered under the wrong type")),
+            None => self.world.storage_mut::<C>(id).remove(self.entity).map(ComponentCell::into_inner),
+        }
+    }
+}
+
+impl<'w> Drop for BundleTaker<'w> {
+    /// Table values nobody took are dropped along with `columns`, the rest still sit in their storages
+    fn drop(&mut self) {
+        for id in &self.had {
+            if let Some(storage) = self.world.storages.get_mut(id) {
+                storage.remove_entity(self.entity);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+
+    #[derive(Debug, PartialEq)]
+    struct Name(&'static str);
+
+    #[test]
+    fn components_are_stored_per_entity() {
+        let mut world = World::new();
+        let a = world.spawn_empty();
+        let b = world.spawn_empty();
+        assert_eq!(world.insert(a, Position(1)), None);
+        world.insert(a, Name("a"));
+        world.insert(b, Position(2));
+
+        assert_eq!(world.get::<Position>(a), Some(&Position(1)));
+        assert_eq!(world.get::<Name>(a), Some(&Name("a")));
+        assert_eq!(world.get::<Position>(b), Some(&Position(2)));
+        assert_eq!(world.get::<Name>(b), None);
+        assert_eq!(world.len(), 2);
+    }
+
+    #[test]
+    fn insert_replaces_and_remove_detaches() {
+        let mut world = World::new();
+        let entity = world.spawn_empty();
+        world.insert(entity, Position(1));
+        assert_eq!(world.insert(entity, Position(2)), Some(Position(1)));
+        world.get_mut::<Position>(entity).unwrap().0 += 1;
+        assert_eq!(world.get::<Position>(entity), Some(&Position(3)));
+
+        assert_eq!(world.remove::<Position>(entity), Some(Position(3)));
+        assert_eq!(world.remove::<Position>(entity), None);
+        assert_eq!(world.get::<Position>(entity), None);
+        assert!(world.contains(entity));
+    }
+
+    #[test]
+    fn despawned_entities_lose_their_components() {
+        let mut world = World::new();
+        let old = world.spawn((Position(1), Name("old")));
+        assert!(world.despawn(old));
+        assert!(!world.despawn(old));
+        assert!(!world.contains(old));
+        assert_eq!(world.get::<Position>(old), None);
+
+        // The slot is reused, the stale Entity still misses
+        let new = world.spawn_empty();
+        assert_eq!(new.index(), old.index());
+        assert_eq!(world.get::<Position>(new), None);
+        assert_eq!(world.get::<Position>(old), None);
+        assert_eq!(world.remove::<Name>(old), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not exist")]
+    fn inserting_on_a_despawned_entity_panics() {
+        let mut world = World::new();
+        let entity = world.spawn_empty();
+        world.despawn(entity);
+        world.insert(entity, Position(1));
+    }
+
+    #[test]
+    fn reserved_entities_become_valid_after_flush() {
+        let mut world = World::new();
+        let spawned = world.spawn(());
+        world.despawn(spawned);
+        let reused = world.reserve_entity();
+        let fresh = world.reserve_entity();
+        assert_eq!(reused.index(), spawned.index());
+        assert!(!world.contains(reused) && !world.contains(fresh));
+
+        world.flush();
+        assert!(world.contains(reused) && world.contains(fresh));
+        assert_eq!(world.location(fresh).unwrap().archetype, ArchetypeId::EMPTY);
+        world.insert(fresh, 5u32);
+        assert_eq!(world.get::<u32>(fresh), Some(&5));
+
+        // Anything that changes the World flushes first
+        let pending = world.reserve_entity();
+        world.spawn(());
+        assert!(world.contains(pending));
+    }
+}
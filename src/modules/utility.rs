@@ -1,5 +1,6 @@
 pub mod constant;
 pub mod debug;
+pub mod math;
 pub mod share;
 pub mod utility;
 pub mod structs;
\ No newline at end of file
@@ -0,0 +1,285 @@
+//! Just enough linear algebra for placing things in 3D, laid out the way GLSL expects it
+
+use std::ops::{ Add, AddAssign, Mul, Neg, Sub, SubAssign };
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub const ZERO: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+    pub const ONE: Vec3 = Vec3::new(1.0, 1.0, 1.0);
+    pub const X: Vec3 = Vec3::new(1.0, 0.0, 0.0);
+    pub const Y: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    pub const Z: Vec3 = Vec3::new(0.0, 0.0, 1.0);
+
+    pub const fn new(x: f32, y: f32, z: f32) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    pub const fn splat(value: f32) -> Vec3 {
+        Vec3::new(value, value, value)
+    }
+
+    pub fn dot(self, other: Vec3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    /// Zero stays zero instead of turning into NaN
+    pub fn normalize(self) -> Vec3 {
+        let length = self.length();
+        if length == 0.0 {
+            self
+        } else {
+            self * (1.0 / length)
+        }
+    }
+
+    /// Component wise product
+    pub fn scale(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x * other.x, self.y * other.y, self.z * other.z)
+    }
+
+    pub fn to_array(self) -> [f32; 3] {
+        [self.x, self.y, self.z]
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, other: Vec3) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl SubAssign for Vec3 {
+    fn sub_assign(&mut self, other: Vec3) {
+        *self = *self - other;
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, scalar: f32) -> Vec3 {
+        Vec3::new(self.x * scalar, self.y * scalar, self.z * scalar)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+/// Unit quaternion describing a rotation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Default for Quat {
+    fn default() -> Quat {
+        Quat::IDENTITY
+    }
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat { x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
+
+    /// Rotation of `angle` radians around `axis`, which is normalized first
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Quat {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        Quat {
+            x: axis.x * sin,
+            y: axis.y * sin,
+            z: axis.z * sin,
+            w: cos,
+        }
+    }
+
+    pub fn from_rotation_x(angle: f32) -> Quat {
+        Quat::from_axis_angle(Vec3::X, angle)
+    }
+
+    pub fn from_rotation_y(angle: f32) -> Quat {
+        Quat::from_axis_angle(Vec3::Y, angle)
+    }
+
+    pub fn from_rotation_z(angle: f32) -> Quat {
+        Quat::from_axis_angle(Vec3::Z, angle)
+    }
+
+    pub fn length(self) -> f32 {
+        (self.x * self.x + self.y * self.y + self.z * self.z + self.w * self.w).sqrt()
+    }
+
+    pub fn normalize(self) -> Quat {
+        let length = self.length();
+        if length == 0.0 {
+            return Quat::IDENTITY;
+        }
+        let inverse = 1.0 / length;
+        Quat {
+            x: self.x * inverse,
+            y: self.y * inverse,
+            z: self.z * inverse,
+            w: self.w * inverse,
+        }
+    }
+
+    /// The opposite rotation, assuming this one is normalized
+    pub fn inverse(self) -> Quat {
+        Quat {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+            w: self.w,
+        }
+    }
+}
+
+/// `a * b` rotates by `b` first and then by `a`
+impl Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, other: Quat) -> Quat {
+        Quat {
+            x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            y: self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            z: self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+            w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+        }
+    }
+}
+
+impl Mul<Vec3> for Quat {
+    type Output = Vec3;
+
+    fn mul(self, v: Vec3) -> Vec3 {
+        let q = Vec3::new(self.x, self.y, self.z);
+        let t = q.cross(v) * 2.0;
+        v + t * self.w + q.cross(t)
+    }
+}
+
+/// Column major 4x4 matrix, `cols[c][r]`, matching what Vulkan shaders expect in a uniform buffer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+    pub cols: [[f32; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Mat4 {
+        Mat4::IDENTITY
+    }
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4 {
+        cols: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    /// Scales first, then rotates, then translates
+    pub fn from_scale_rotation_translation(scale: Vec3, rotation: Quat, translation: Vec3) -> Mat4 {
+        let x = rotation * Vec3::X * scale.x;
+        let y = rotation * Vec3::Y * scale.y;
+        let z = rotation * Vec3::Z * scale.z;
+        Mat4 {
+            cols: [
+                [x.x, x.y, x.z, 0.0],
+                [y.x, y.y, y.z, 0.0],
+                [z.x, z.y, z.z, 0.0],
+                [translation.x, translation.y, translation.z, 1.0],
+            ],
+        }
+    }
+
+    pub fn from_translation(translation: Vec3) -> Mat4 {
+        Mat4::from_scale_rotation_translation(Vec3::ONE, Quat::IDENTITY, translation)
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        let w = self.cols[3];
+        Vec3::new(w[0], w[1], w[2])
+    }
+
+    /// Applies the full transform, translation included
+    pub fn transform_point3(&self, p: Vec3) -> Vec3 {
+        self.transform_vector3(p) + self.translation()
+    }
+
+    /// Applies only the linear part, for directions
+    pub fn transform_vector3(&self, v: Vec3) -> Vec3 {
+        let c = &self.cols;
+        Vec3::new(
+            c[0][0] * v.x + c[1][0] * v.y + c[2][0] * v.z,
+            c[0][1] * v.x + c[1][1] * v.y + c[2][1] * v.z,
+            c[0][2] * v.x + c[1][2] * v.y + c[2][2] * v.z,
+        )
+    }
+
+    pub fn to_cols_array(&self) -> [f32; 16] {
+        let mut out = [0.0; 16];
+        for (c, col) in self.cols.iter().enumerate() {
+            out[c * 4..c * 4 + 4].copy_from_slice(col);
+        }
+        out
+    }
+}
+
+/// `a * b` applies `b` first and then `a`
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut cols = [[0.0; 4]; 4];
+        for (c, col) in cols.iter_mut().enumerate() {
+            for (r, value) in col.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.cols[k][r] * other.cols[c][k]).sum();
+            }
+        }
+        Mat4 { cols }
+    }
+}